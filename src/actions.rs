@@ -0,0 +1,657 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::tools::{PaintTool, SelectedTool};
+use crate::{SelectedTileType, TileType};
+
+/// Where bindings are kept, relative to the user's config directory.
+const BINDINGS_FILE: &str = "aztlan-garden/bindings.json";
+
+/// Something the user can trigger with a key, mouse button or gamepad
+/// button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Paint,
+    SelectTileType(TileType),
+    NextTileType,
+    PreviousTileType,
+    SelectTool(PaintTool),
+    /// Selects the type of the tile under the cursor.
+    PickTileType,
+    /// Resets the tile under the cursor to the erase type.
+    Erase,
+    Undo,
+    Redo,
+    FitMap,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    OpenSettings,
+}
+
+impl Action {
+    pub fn label(&self) -> String {
+        match self {
+            Action::Paint => "Paint".to_string(),
+            Action::SelectTileType(tile_type) => format!("Select {tile_type:?}"),
+            Action::NextTileType => "Next tile type".to_string(),
+            Action::PreviousTileType => "Previous tile type".to_string(),
+            Action::SelectTool(tool) => format!("{} tool", tool.label()),
+            Action::PickTileType => "Pick tile type".to_string(),
+            Action::Erase => "Erase tile".to_string(),
+            Action::Undo => "Undo".to_string(),
+            Action::Redo => "Redo".to_string(),
+            Action::FitMap => "Fit map".to_string(),
+            Action::PanUp => "Pan up".to_string(),
+            Action::PanDown => "Pan down".to_string(),
+            Action::PanLeft => "Pan left".to_string(),
+            Action::PanRight => "Pan right".to_string(),
+            Action::OpenSettings => "Key bindings".to_string(),
+        }
+    }
+}
+
+/// A physical button an action can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum InputButton {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+/// A button together with the modifiers that must be held with it.
+/// Modifiers must match exactly when the button is pressed, so Ctrl+Z and
+/// Ctrl+Shift+Z are different bindings. After that the action stays held
+/// until the button is released, whatever happens to the modifiers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Binding {
+    pub button: InputButton,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+}
+
+impl Binding {
+    pub const fn key(key: KeyCode) -> Self {
+        Binding {
+            button: InputButton::Key(key),
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    pub const fn ctrl(key: KeyCode) -> Self {
+        Binding {
+            button: InputButton::Key(key),
+            ctrl: true,
+            shift: false,
+        }
+    }
+
+    pub const fn mouse(button: MouseButton) -> Self {
+        Binding {
+            button: InputButton::Mouse(button),
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    pub const fn gamepad(button: GamepadButtonType) -> Self {
+        Binding {
+            button: InputButton::Gamepad(button),
+            ctrl: false,
+            shift: false,
+        }
+    }
+
+    pub const fn shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    /// Short human-readable form, e.g. `Ctrl+Shift+Z` or `Mouse Left`.
+    pub fn label(&self) -> String {
+        let button = match self.button {
+            InputButton::Key(key) => {
+                let name = format!("{key:?}");
+                match name.strip_prefix("Key").or(name.strip_prefix("Digit")) {
+                    Some(short) => short.to_string(),
+                    None => name,
+                }
+            }
+            InputButton::Mouse(button) => format!("Mouse {button:?}"),
+            InputButton::Gamepad(button) => format!("Pad {button:?}"),
+        };
+        let ctrl = if self.ctrl { "Ctrl+" } else { "" };
+        let shift = if self.shift { "Shift+" } else { "" };
+        format!("{ctrl}{shift}{button}")
+    }
+}
+
+/// Snapshot of every input device, so bindings can be checked without
+/// passing three resources around.
+struct Inputs<'a> {
+    keys: &'a ButtonInput<KeyCode>,
+    mouse: &'a ButtonInput<MouseButton>,
+    gamepad: &'a ButtonInput<GamepadButton>,
+}
+
+impl Inputs<'_> {
+    fn modifiers_match(&self, binding: &Binding) -> bool {
+        let ctrl = self
+            .keys
+            .any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+        let shift = self
+            .keys
+            .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        binding.ctrl == ctrl && binding.shift == shift
+    }
+
+    /// Whether the button is down, with or without the modifiers.
+    fn held(&self, binding: &Binding) -> bool {
+        match binding.button {
+            InputButton::Key(key) => self.keys.pressed(key),
+            InputButton::Mouse(button) => self.mouse.pressed(button),
+            InputButton::Gamepad(button) => self
+                .gamepad
+                .get_pressed()
+                .any(|pressed| pressed.button_type == button),
+        }
+    }
+
+    fn just_pressed(&self, binding: &Binding) -> bool {
+        let just_pressed = match binding.button {
+            InputButton::Key(key) => self.keys.just_pressed(key),
+            InputButton::Mouse(button) => self.mouse.just_pressed(button),
+            InputButton::Gamepad(button) => self
+                .gamepad
+                .get_just_pressed()
+                .any(|pressed| pressed.button_type == button),
+        };
+        just_pressed && self.modifiers_match(binding)
+    }
+}
+
+/// Bindings for one action. An action may have several, such as WASD and
+/// the arrow keys for panning.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActionBindings {
+    pub action: Action,
+    pub bindings: Vec<Binding>,
+}
+
+/// Every binding in the editor, loaded from the user's bindings file.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ActionMap {
+    actions: Vec<ActionBindings>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        const DIGITS: [KeyCode; 9] = [
+            KeyCode::Digit1,
+            KeyCode::Digit2,
+            KeyCode::Digit3,
+            KeyCode::Digit4,
+            KeyCode::Digit5,
+            KeyCode::Digit6,
+            KeyCode::Digit7,
+            KeyCode::Digit8,
+            KeyCode::Digit9,
+        ];
+        let tool_keys = [
+            (PaintTool::Brush, KeyCode::KeyB),
+            (PaintTool::Fill, KeyCode::KeyG),
+            (PaintTool::Rectangle, KeyCode::KeyR),
+            (PaintTool::RectangleOutline, KeyCode::KeyO),
+            (PaintTool::Line, KeyCode::KeyL),
+        ];
+
+        let mut actions = vec![(
+            Action::Paint,
+            vec![
+                Binding::mouse(MouseButton::Left),
+                Binding::gamepad(GamepadButtonType::South),
+            ],
+        )];
+        actions.extend(
+            DIGITS
+                .into_iter()
+                .zip(TileType::ALL)
+                .map(|(key, tile_type)| {
+                    (Action::SelectTileType(tile_type), vec![Binding::key(key)])
+                }),
+        );
+        actions.extend([
+            (
+                Action::NextTileType,
+                vec![
+                    Binding::key(KeyCode::Tab),
+                    Binding::gamepad(GamepadButtonType::RightTrigger),
+                ],
+            ),
+            (
+                Action::PreviousTileType,
+                vec![
+                    Binding::key(KeyCode::Tab).shift(),
+                    Binding::gamepad(GamepadButtonType::LeftTrigger),
+                ],
+            ),
+        ]);
+        actions.extend(
+            tool_keys
+                .into_iter()
+                .map(|(tool, key)| (Action::SelectTool(tool), vec![Binding::key(key)])),
+        );
+        actions.extend([
+            (
+                Action::PickTileType,
+                vec![Binding::mouse(MouseButton::Right)],
+            ),
+            (
+                Action::Erase,
+                vec![Binding::mouse(MouseButton::Right).shift()],
+            ),
+            (
+                Action::Undo,
+                vec![
+                    Binding::ctrl(KeyCode::KeyZ),
+                    Binding::gamepad(GamepadButtonType::West),
+                ],
+            ),
+            (
+                Action::Redo,
+                vec![
+                    Binding::ctrl(KeyCode::KeyZ).shift(),
+                    Binding::gamepad(GamepadButtonType::North),
+                ],
+            ),
+            (Action::FitMap, vec![Binding::key(KeyCode::KeyF)]),
+            (
+                Action::PanUp,
+                vec![
+                    Binding::key(KeyCode::KeyW),
+                    Binding::key(KeyCode::ArrowUp),
+                    Binding::gamepad(GamepadButtonType::DPadUp),
+                ],
+            ),
+            (
+                Action::PanDown,
+                vec![
+                    Binding::key(KeyCode::KeyS),
+                    Binding::key(KeyCode::ArrowDown),
+                    Binding::gamepad(GamepadButtonType::DPadDown),
+                ],
+            ),
+            (
+                Action::PanLeft,
+                vec![
+                    Binding::key(KeyCode::KeyA),
+                    Binding::key(KeyCode::ArrowLeft),
+                    Binding::gamepad(GamepadButtonType::DPadLeft),
+                ],
+            ),
+            (
+                Action::PanRight,
+                vec![
+                    Binding::key(KeyCode::KeyD),
+                    Binding::key(KeyCode::ArrowRight),
+                    Binding::gamepad(GamepadButtonType::DPadRight),
+                ],
+            ),
+            (Action::OpenSettings, vec![Binding::key(KeyCode::F1)]),
+        ]);
+
+        ActionMap {
+            actions: actions
+                .into_iter()
+                .map(|(action, bindings)| ActionBindings { action, bindings })
+                .collect(),
+        }
+    }
+}
+
+impl ActionMap {
+    /// Loads bindings saved by [`ActionMap::save`]. Actions missing from the
+    /// file, e.g. ones added since it was written, keep their defaults.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let saved: ActionMap = serde_json::from_str(&contents).map_err(|err| err.to_string())?;
+
+        let mut map = ActionMap::default();
+        for entry in &mut map.actions {
+            if let Some(saved) = saved
+                .actions
+                .iter()
+                .find(|saved| saved.action == entry.action)
+            {
+                entry.bindings = saved.bindings.clone();
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let contents = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        fs::write(path, contents).map_err(|err| err.to_string())
+    }
+
+    pub fn actions(&self) -> &[ActionBindings] {
+        &self.actions
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.actions
+            .iter()
+            .find(|entry| entry.action == action)
+            .map_or(&[], |entry| &entry.bindings)
+    }
+
+    /// Binds `action` to `binding` in place of its binding number `slot`,
+    /// or as an extra binding when it has fewer.
+    pub fn rebind(&mut self, action: Action, slot: usize, binding: Binding) {
+        let Some(entry) = self.actions.iter_mut().find(|entry| entry.action == action) else {
+            return;
+        };
+        match entry.bindings.get_mut(slot) {
+            Some(existing) => *existing = binding,
+            None => entry.bindings.push(binding),
+        }
+    }
+
+    /// Removes binding number `slot` from `action`.
+    pub fn unbind(&mut self, action: Action, slot: usize) {
+        if let Some(entry) = self.actions.iter_mut().find(|entry| entry.action == action)
+            && slot < entry.bindings.len()
+        {
+            entry.bindings.remove(slot);
+        }
+    }
+
+    /// Other actions already bound to `binding`.
+    pub fn conflicts(&self, action: Action, binding: &Binding) -> Vec<Action> {
+        self.actions
+            .iter()
+            .filter(|entry| entry.action != action && entry.bindings.contains(binding))
+            .map(|entry| entry.action)
+            .collect()
+    }
+}
+
+/// Bindings file chosen with `--bindings`, or the one in the user's config
+/// directory.
+#[derive(Resource)]
+pub struct BindingsPath(pub PathBuf);
+
+impl BindingsPath {
+    /// `$XDG_CONFIG_HOME`, falling back to `~/.config` and finally to the
+    /// working directory.
+    pub fn user_default() -> Self {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default();
+        BindingsPath(config_dir.join(BINDINGS_FILE))
+    }
+}
+
+/// Loads the user's bindings, keeping the defaults when the file is missing
+/// or cannot be read.
+pub fn load_bindings(path: &Path) -> ActionMap {
+    if !path.exists() {
+        return ActionMap::default();
+    }
+    match ActionMap::load(path) {
+        Ok(map) => map,
+        Err(err) => {
+            warn!(
+                "ignoring corrupt bindings file {}: {err}; using the defaults",
+                path.display()
+            );
+            ActionMap::default()
+        }
+    }
+}
+
+/// Which actions are held this frame, for actions like painting that last
+/// as long as their button is down.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+}
+
+impl ActionState {
+    /// Reads this frame's input and returns the actions that started.
+    fn update(&mut self, actions: &[ActionBindings], inputs: &Inputs) -> Vec<Action> {
+        let mut pressed = HashSet::new();
+        let mut started = Vec::new();
+        for entry in actions {
+            if entry
+                .bindings
+                .iter()
+                .any(|binding| inputs.just_pressed(binding))
+            {
+                started.push(entry.action);
+                pressed.insert(entry.action);
+            }
+            // Pressing Shift mid-stroke must not end the stroke.
+            if self.pressed(entry.action)
+                && entry.bindings.iter().any(|binding| inputs.held(binding))
+            {
+                pressed.insert(entry.action);
+            }
+        }
+
+        self.just_released = self.pressed.difference(&pressed).copied().collect();
+        self.just_pressed = started.iter().copied().collect();
+        self.pressed = pressed;
+        started
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+}
+
+/// Fired once when the binding for an action is pressed.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ActionEvent(pub Action);
+
+/// While set, input is not turned into actions, e.g. while the settings
+/// screen is waiting for a new binding.
+#[derive(Resource, Default)]
+pub struct ActionsSuspended(pub bool);
+
+/// Turns button presses into [`ActionState`] and [`ActionEvent`]s.
+pub fn action_input_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepad: Res<ButtonInput<GamepadButton>>,
+    actions: Res<ActionMap>,
+    suspended: Res<ActionsSuspended>,
+    mut state: ResMut<ActionState>,
+    mut events: EventWriter<ActionEvent>,
+) {
+    let inputs = Inputs {
+        keys: &keys,
+        mouse: &mouse,
+        gamepad: &gamepad,
+    };
+    // Suspending releases every action, as if no button were down.
+    let bindings: &[ActionBindings] = if suspended.0 { &[] } else { actions.actions() };
+    for action in state.update(bindings, &inputs) {
+        events.send(ActionEvent(action));
+    }
+}
+
+/// Applies the palette and tool actions.
+pub fn palette_action_system(
+    mut events: EventReader<ActionEvent>,
+    mut selected: ResMut<SelectedTileType>,
+    mut tool: ResMut<SelectedTool>,
+) {
+    let step = |tile_type: TileType, offset: usize| {
+        let index = TileType::ALL
+            .iter()
+            .position(|t| *t == tile_type)
+            .unwrap_or(0);
+        TileType::ALL[(index + offset) % TileType::ALL.len()]
+    };
+    for ActionEvent(action) in events.read() {
+        match *action {
+            Action::SelectTileType(tile_type) => selected.0 = tile_type,
+            Action::NextTileType => selected.0 = step(selected.0, 1),
+            Action::PreviousTileType => selected.0 = step(selected.0, TileType::ALL.len() - 1),
+            Action::SelectTool(paint_tool) => tool.0 = paint_tool,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Devices {
+        keys: ButtonInput<KeyCode>,
+        mouse: ButtonInput<MouseButton>,
+        gamepad: ButtonInput<GamepadButton>,
+    }
+
+    impl Devices {
+        /// Runs one frame of [`ActionState::update`], then ages the input
+        /// like Bevy does between frames.
+        fn frame(&mut self, state: &mut ActionState, actions: &ActionMap) -> Vec<Action> {
+            let inputs = Inputs {
+                keys: &self.keys,
+                mouse: &self.mouse,
+                gamepad: &self.gamepad,
+            };
+            let started = state.update(actions.actions(), &inputs);
+            self.keys.clear();
+            self.mouse.clear();
+            self.gamepad.clear();
+            started
+        }
+    }
+
+    #[test]
+    fn modifiers_during_a_drag_keep_the_action_held() {
+        let actions = ActionMap::default();
+        let mut state = ActionState::default();
+        let mut devices = Devices::default();
+
+        devices.mouse.press(MouseButton::Left);
+        assert_eq!(devices.frame(&mut state, &actions), vec![Action::Paint]);
+        assert!(state.pressed(Action::Paint));
+
+        devices.keys.press(KeyCode::ShiftLeft);
+        devices.frame(&mut state, &actions);
+        devices.keys.release(KeyCode::ShiftLeft);
+        devices.keys.press(KeyCode::ControlRight);
+        devices.frame(&mut state, &actions);
+        assert!(state.pressed(Action::Paint));
+        assert!(!state.just_released(Action::Paint));
+
+        devices.mouse.release(MouseButton::Left);
+        devices.frame(&mut state, &actions);
+        assert!(!state.pressed(Action::Paint));
+        assert!(state.just_released(Action::Paint));
+    }
+
+    #[test]
+    fn modifiers_must_match_on_press() {
+        let actions = ActionMap::default();
+        let mut state = ActionState::default();
+        let mut devices = Devices::default();
+
+        devices.keys.press(KeyCode::ShiftLeft);
+        devices.mouse.press(MouseButton::Right);
+        assert_eq!(devices.frame(&mut state, &actions), vec![Action::Erase]);
+        assert!(!state.pressed(Action::PickTileType));
+
+        // Letting go of Shift does not turn the erase into a pick.
+        devices.keys.release(KeyCode::ShiftLeft);
+        assert!(devices.frame(&mut state, &actions).is_empty());
+        assert!(state.pressed(Action::Erase));
+        assert!(!state.pressed(Action::PickTileType));
+    }
+
+    #[test]
+    fn corrupt_bindings_fall_back_to_the_defaults() {
+        let path = env::temp_dir().join(format!(
+            "aztlan-bindings-{}-corrupt.json",
+            std::process::id()
+        ));
+        fs::write(&path, "[{ \"action\": \"Paint\", ").unwrap();
+        assert!(ActionMap::load(&path).is_err());
+        assert_eq!(load_bindings(&path), ActionMap::default());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(load_bindings(&path), ActionMap::default());
+    }
+
+    #[test]
+    fn saved_bindings_override_only_their_actions() {
+        let path = env::temp_dir().join(format!(
+            "aztlan-bindings-{}-partial.json",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            r#"[{ "action": "FitMap", "bindings": [{ "button": { "Key": "KeyM" } }] }]"#,
+        )
+        .unwrap();
+        let map = load_bindings(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(map.bindings(Action::FitMap), &[Binding::key(KeyCode::KeyM)]);
+        let defaults = ActionMap::default();
+        assert_eq!(map.bindings(Action::Undo), defaults.bindings(Action::Undo));
+    }
+
+    #[test]
+    fn conflicts_list_other_actions_on_the_same_binding() {
+        let mut map = ActionMap::default();
+        let tab = Binding::key(KeyCode::Tab);
+        assert_eq!(
+            map.conflicts(Action::FitMap, &tab),
+            vec![Action::NextTileType]
+        );
+        assert!(map.conflicts(Action::NextTileType, &tab).is_empty());
+
+        // Modifiers make a different binding.
+        assert_eq!(
+            map.conflicts(Action::FitMap, &tab.shift()),
+            vec![Action::PreviousTileType]
+        );
+        assert!(
+            map.conflicts(Action::FitMap, &Binding::ctrl(KeyCode::Tab))
+                .is_empty()
+        );
+
+        map.rebind(Action::FitMap, 0, tab);
+        assert_eq!(map.conflicts(Action::Undo, &tab).len(), 2);
+    }
+}
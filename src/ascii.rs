@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::{error::Error, fmt, path::Path};
+
+use crate::TileType;
+use crate::generation::generate_tile;
+use crate::grid::GridConfig;
+use crate::history::TileState;
+use crate::map::{MAP_FORMAT_VERSION, MapFile, MapTile};
+
+/// Maps saved with this extension use the text format instead of JSON.
+const ASCII_EXTENSION: &str = "txt";
+
+/// One character per tile, one line per row with the north edge on the first
+/// line:
+///
+/// ```text
+/// ~~~..
+/// ~=:*.
+/// ..:*.
+/// ```
+///
+/// Only tile types are stored, so crops load freshly seeded on dirt.
+const TILE_CHARS: [(TileType, char); 5] = [
+    (TileType::Grass, '.'),
+    (TileType::Dirt, ':'),
+    (TileType::Water, '~'),
+    (TileType::Crop, '*'),
+    (TileType::Chinampa, '='),
+];
+
+pub fn is_ascii_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == ASCII_EXTENSION)
+}
+
+fn tile_char(tile_type: TileType) -> char {
+    TILE_CHARS
+        .iter()
+        .find(|(t, _)| *t == tile_type)
+        .map_or('?', |(_, c)| *c)
+}
+
+fn char_tile(c: char) -> Option<TileType> {
+    TILE_CHARS.iter().find(|(_, ch)| *ch == c).map(|(t, _)| *t)
+}
+
+/// Where a text map stopped making sense, counted from 1.
+#[derive(Debug, PartialEq)]
+pub struct AsciiError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for AsciiError {}
+
+pub fn parse_ascii(text: &str) -> Result<MapFile, AsciiError> {
+    let error = |line: usize, column: usize, message: String| AsciiError {
+        line: line + 1,
+        column,
+        message,
+    };
+    let rows: Vec<&str> = text.trim_end_matches(['\r', '\n']).lines().collect();
+    let Some(first) = rows.first().filter(|row| !row.is_empty()) else {
+        return Err(error(0, 1, "map is empty".to_string()));
+    };
+    let width = first.chars().count();
+    let height = rows.len();
+
+    let mut tiles = Vec::with_capacity(width * height);
+    for (line, row) in rows.iter().enumerate() {
+        let y = (height - 1 - line) as u32;
+        let mut row_width = 0;
+        for (index, c) in row.chars().enumerate() {
+            let Some(tile_type) = char_tile(c) else {
+                return Err(error(line, index + 1, format!("unknown tile `{c}`")));
+            };
+            if index >= width {
+                break;
+            }
+            let state = TileState {
+                tile_type,
+                growth: None,
+            };
+            tiles.push(MapTile::new(index as u32, y, state));
+            row_width = index + 1;
+        }
+        let actual = row.chars().count();
+        if actual != width {
+            return Err(error(
+                line,
+                row_width + 1,
+                format!("row is {actual} tiles wide but the first row is {width}"),
+            ));
+        }
+    }
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+
+    Ok(MapFile {
+        version: MAP_FORMAT_VERSION,
+        width: width as u32,
+        height: height as u32,
+        seed: None,
+        terrain: None,
+        tiles,
+    })
+}
+
+/// Writes every tile of `map`, generating the ones a seeded map leaves out.
+pub fn to_ascii(map: &MapFile) -> String {
+    let listed: HashMap<(u32, u32), TileType> = map
+        .tiles
+        .iter()
+        .map(|tile| ((tile.x, tile.y), tile.tile_type))
+        .collect();
+    let grid = GridConfig {
+        width: map.width,
+        height: map.height,
+        ..Default::default()
+    };
+    let terrain = map.terrain.unwrap_or_default();
+
+    let mut text = String::with_capacity(((map.width + 1) * map.height) as usize);
+    for y in (0..map.height).rev() {
+        for x in 0..map.width {
+            let tile_type = match (listed.get(&(x, y)), map.seed) {
+                (Some(tile_type), _) => *tile_type,
+                (None, Some(seed)) => generate_tile(&grid, seed, &terrain, x, y),
+                // Validated maps without a seed list every tile.
+                (None, None) => TileType::Grass,
+            };
+            text.push(tile_char(tile_type));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generation::TerrainParams;
+
+    #[test]
+    fn first_line_is_the_north_edge() {
+        let map = parse_ascii("~~.\n:*=\n").unwrap();
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.type_at(0, 1), TileType::Water);
+        assert_eq!(map.type_at(2, 1), TileType::Grass);
+        assert_eq!(map.type_at(0, 0), TileType::Dirt);
+        assert_eq!(map.type_at(1, 0), TileType::Crop);
+        assert_eq!(map.type_at(2, 0), TileType::Chinampa);
+        assert!(map.validate().is_ok());
+    }
+
+    #[test]
+    fn saving_round_trips() {
+        let text = "\
+~~~..
+~=:*.
+..:*.
+";
+        assert_eq!(to_ascii(&parse_ascii(text).unwrap()), text);
+    }
+
+    #[test]
+    fn reports_unknown_tiles_by_line_and_column() {
+        let err = parse_ascii("...\n.x.\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 2));
+        assert_eq!(err.to_string(), "line 2, column 2: unknown tile `x`");
+    }
+
+    #[test]
+    fn reports_ragged_rows() {
+        let short = parse_ascii("...\n..\n").unwrap_err();
+        assert_eq!((short.line, short.column), (2, 3));
+
+        let long = parse_ascii("...\n....\n").unwrap_err();
+        assert_eq!((long.line, long.column), (2, 4));
+    }
+
+    #[test]
+    fn rejects_empty_maps() {
+        assert_eq!(parse_ascii("\n").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn writes_the_seeded_tiles_between_listed_ones() {
+        let terrain = TerrainParams {
+            water_level: 0.4,
+            ..Default::default()
+        };
+        let map = MapFile {
+            version: MAP_FORMAT_VERSION,
+            width: 6,
+            height: 5,
+            seed: Some(7),
+            terrain: Some(terrain),
+            tiles: vec![MapTile::new(
+                2,
+                3,
+                TileState {
+                    tile_type: TileType::Chinampa,
+                    growth: None,
+                },
+            )],
+        };
+        let grid = GridConfig {
+            width: 6,
+            height: 5,
+            ..Default::default()
+        };
+
+        let written = parse_ascii(&to_ascii(&map)).unwrap();
+        for y in 0..5 {
+            for x in 0..6 {
+                let expected = match (x, y) {
+                    (2, 3) => TileType::Chinampa,
+                    _ => generate_tile(&grid, 7, &terrain, x, y),
+                };
+                assert_eq!(written.type_at(x, y), expected, "tile ({x}, {y})");
+            }
+        }
+    }
+}
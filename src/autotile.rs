@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::grid::{GridConfig, TileGrid};
+use crate::{Tile, TilePosition, TileType};
+
+pub const NORTH: u8 = 1;
+pub const EAST: u8 = 2;
+pub const SOUTH: u8 = 4;
+pub const WEST: u8 = 8;
+
+/// Which orthogonal neighbours continue the same terrain, as a set of
+/// [`NORTH`], [`EAST`], [`SOUTH`] and [`WEST`] bits. A tile with all four
+/// set sits inside its region; any missing bit is an edge the tileset can
+/// draw a shoreline or border on.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutotileMask(pub u8);
+
+impl Default for AutotileMask {
+    fn default() -> Self {
+        AutotileMask(NORTH | EAST | SOUTH | WEST)
+    }
+}
+
+impl AutotileMask {
+    pub fn is_interior(&self) -> bool {
+        *self == AutotileMask::default()
+    }
+}
+
+/// Mask for a tile of `tile_type` given a lookup of the types around it.
+/// Neighbours off the map or in chunks that are not loaded count as
+/// connected, so no edge is drawn along the border of the known world.
+pub fn autotile_mask(
+    tile_type: TileType,
+    neighbor: impl Fn(i32, i32) -> Option<TileType>,
+) -> AutotileMask {
+    // World y grows upwards, so north is the row above.
+    let mask = [(0, 1, NORTH), (1, 0, EAST), (0, -1, SOUTH), (-1, 0, WEST)]
+        .into_iter()
+        .filter(|(dx, dy, _)| neighbor(*dx, *dy).is_none_or(|other| other == tile_type))
+        .fold(0, |mask, (_, _, bit)| mask | bit);
+    AutotileMask(mask)
+}
+
+/// Recomputes the masks of tiles whose type changed, and of their
+/// neighbours, so edges follow the brush as the garden is painted. Runs a
+/// frame behind the change so freshly spawned chunks are in [`TileGrid`].
+pub fn autotile_system(
+    grid: Res<GridConfig>,
+    tile_grid: Res<TileGrid>,
+    tiles: Query<(&TilePosition, Ref<TileType>), With<Tile>>,
+    mut masks: Query<&mut AutotileMask>,
+) {
+    let mut dirty = HashSet::new();
+    for (pos, tile_type) in &tiles {
+        if tile_type.is_changed() {
+            dirty.insert((pos.x, pos.y));
+            dirty.extend(grid.neighbors(pos.x, pos.y));
+        }
+    }
+
+    let type_at = |x: u32, y: u32| {
+        let entity = tile_grid.get(x, y)?;
+        tiles.get(entity).ok().map(|(_, tile_type)| *tile_type)
+    };
+    for (x, y) in dirty {
+        let (Some(entity), Some(tile_type)) = (tile_grid.get(x, y), type_at(x, y)) else {
+            continue;
+        };
+        let mask = autotile_mask(tile_type, |dx, dy| {
+            let nx = x.checked_add_signed(dx)?;
+            let ny = y.checked_add_signed(dy)?;
+            type_at(nx, ny)
+        });
+        if let Ok(mut current) = masks.get_mut(entity) {
+            current.set_if_neq(mask);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+
+use crate::actions::{Action, ActionEvent, ActionState};
+use crate::grid::GridConfig;
+
+/// Closest the camera can zoom in, as an orthographic projection scale.
+pub const MIN_ZOOM: f32 = 0.25;
+/// Furthest the camera can zoom out. Also bounds how many chunks are
+/// streamed in at once.
+pub const MAX_ZOOM: f32 = 4.0;
+
+/// Keyboard panning speed in screen pixels per second.
+const PAN_SPEED: f32 = 600.0;
+/// Zoom factor applied per line of scroll.
+const ZOOM_STEP: f32 = 1.1;
+/// Scroll distance that counts as one line on touchpads reporting pixels.
+const PIXELS_PER_LINE: f32 = 20.0;
+/// Height of the two tool bars along the top of the window.
+const TOOLBAR_HEIGHT: f32 = 100.0;
+/// Empty space left around the map when fitting it to the window.
+const FIT_MARGIN: f32 = 16.0;
+
+/// Pans with the pan actions (WASD or the arrow keys by default) or by
+/// dragging with the middle mouse button.
+pub fn camera_pan_system(
+    time: Res<Time>,
+    state: Res<ActionState>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    mut last_cursor: Local<Option<Vec2>>,
+    mut cameras: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let mut direction = Vec2::ZERO;
+    for (action, step) in [
+        (Action::PanUp, Vec2::Y),
+        (Action::PanDown, Vec2::NEG_Y),
+        (Action::PanLeft, Vec2::NEG_X),
+        (Action::PanRight, Vec2::X),
+    ] {
+        if state.pressed(action) {
+            direction += step;
+        }
+    }
+    let mut screen_delta = direction.normalize_or_zero() * PAN_SPEED * time.delta_seconds();
+
+    let cursor = windows.single().cursor_position();
+    if buttons.pressed(MouseButton::Middle) {
+        if let (Some(last), Some(cursor)) = (*last_cursor, cursor) {
+            // Screen y grows downwards, world y upwards.
+            let dragged = cursor - last;
+            screen_delta += Vec2::new(-dragged.x, dragged.y);
+        }
+        *last_cursor = cursor;
+    } else {
+        *last_cursor = None;
+    }
+
+    if screen_delta == Vec2::ZERO {
+        return;
+    }
+    for (mut transform, projection) in &mut cameras {
+        transform.translation += (screen_delta * projection.scale).extend(0.0);
+    }
+}
+
+/// Zooms with the scroll wheel, keeping the world point under the cursor
+/// fixed so the map zooms towards it.
+pub fn camera_zoom_system(
+    mut wheel: EventReader<MouseWheel>,
+    windows: Query<&Window>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    let lines: f32 = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        })
+        .sum();
+    if lines == 0.0 {
+        return;
+    }
+
+    let window = windows.single();
+    let offset = window
+        .cursor_position()
+        .map(|cursor| {
+            Vec2::new(
+                cursor.x - window.width() / 2.0,
+                window.height() / 2.0 - cursor.y,
+            )
+        })
+        .unwrap_or(Vec2::ZERO);
+
+    for (mut transform, mut projection) in &mut cameras {
+        let old_scale = projection.scale;
+        let new_scale = (old_scale * ZOOM_STEP.powf(-lines)).clamp(MIN_ZOOM, MAX_ZOOM);
+        transform.translation += (offset * (old_scale - new_scale)).extend(0.0);
+        projection.scale = new_scale;
+    }
+}
+
+/// Frames the whole map below the tool bars on [`Action::FitMap`], and
+/// whenever the grid is replaced.
+pub fn camera_fit_system(
+    mut events: EventReader<ActionEvent>,
+    grid: Res<GridConfig>,
+    windows: Query<&Window>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    let fit_requested = events
+        .read()
+        .any(|ActionEvent(action)| *action == Action::FitMap);
+    if !grid.is_changed() && !fit_requested {
+        return;
+    }
+
+    let window = windows.single();
+    let view = Vec2::new(window.width(), window.height() - TOOLBAR_HEIGHT) - 2.0 * FIT_MARGIN;
+    let map = Vec2::new(grid.width as f32, grid.height as f32) * grid.tile_size;
+    let scale = (map / view.max(Vec2::ONE))
+        .max_element()
+        .clamp(MIN_ZOOM, MAX_ZOOM);
+
+    // Tiles are centred on their translation, so the map's middle sits half
+    // a tile below and left of the origin. Shift up so the map is centred in
+    // the area under the tool bars rather than in the whole window.
+    let centre = Vec2::splat(-grid.tile_size / 2.0) + Vec2::new(0.0, TOOLBAR_HEIGHT / 2.0 * scale);
+    for (mut transform, mut projection) in &mut cameras {
+        transform.translation = centre.extend(transform.translation.z);
+        projection.scale = scale;
+    }
+}
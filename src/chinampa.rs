@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+
+use crate::crops::CropGrowth;
+use crate::grid::{GridConfig, tile_types};
+use crate::{Tile, TilePosition, TileType};
+
+/// Number of orthogonal canal neighbours a raised bed needs before it earns
+/// the fertility bonus.
+const CANAL_SIDES_FOR_BONUS: usize = 2;
+
+/// Growth multiplier granted to well-surrounded raised beds.
+const CHINAMPA_FERTILITY: f32 = 1.5;
+
+/// Soil fertility of a tile, applied as a multiplier to crop growth.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Fertility(pub f32);
+
+impl Default for Fertility {
+    fn default() -> Self {
+        Fertility(1.0)
+    }
+}
+
+impl Fertility {
+    /// Crops harvested from fertile beds yield double.
+    pub fn harvest_yield(&self) -> u32 {
+        if self.0 > 1.0 { 2 } else { 1 }
+    }
+}
+
+/// Whether the tile is a chinampa, either bare or with a crop planted on it.
+pub fn is_raised_bed(tile_type: &TileType, growth: Option<&CropGrowth>) -> bool {
+    match tile_type {
+        TileType::Chinampa => true,
+        TileType::Crop => growth.is_some_and(|growth| growth.bed == TileType::Chinampa),
+        _ => false,
+    }
+}
+
+/// Grants the fertility bonus to raised beds bordered by canal water on
+/// several sides whenever the layout changes.
+pub fn chinampa_fertility_system(
+    changed: Query<(), (With<Tile>, Changed<TileType>)>,
+    grid: Res<GridConfig>,
+    mut tiles: Query<
+        (
+            &TilePosition,
+            &TileType,
+            Option<&CropGrowth>,
+            &mut Fertility,
+        ),
+        With<Tile>,
+    >,
+) {
+    if changed.is_empty() {
+        return;
+    }
+
+    let types = tile_types(tiles.iter().map(|(pos, tile_type, _, _)| (pos, tile_type)));
+
+    for (pos, tile_type, growth, mut fertility) in &mut tiles {
+        let canal_sides = grid
+            .neighbors(pos.x, pos.y)
+            .filter(|pos| types.get(pos) == Some(&TileType::Water))
+            .count();
+        fertility.0 = if is_raised_bed(tile_type, growth) && canal_sides >= CANAL_SIDES_FOR_BONUS {
+            CHINAMPA_FERTILITY
+        } else {
+            1.0
+        };
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::crops::CropGrowth;
+use crate::generation::{MapSeed, TerrainParams, generate_tile};
+use crate::grid::GridConfig;
+use crate::history::TileState;
+use crate::map::MapFile;
+use crate::moisture::Moisture;
+use crate::{TilePosition, TileType, spawn_tile};
+
+/// Width and height of a chunk, in tiles.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// Chunks loaded beyond the edge of the view, so panning does not reveal
+/// empty space before the next chunk streams in.
+const LOAD_MARGIN: u32 = 1;
+
+/// Chunks further than this beyond the view are despawned. Larger than
+/// [`LOAD_MARGIN`] so chunks near the edge do not flicker in and out.
+const UNLOAD_MARGIN: u32 = 3;
+
+pub type ChunkCoord = (u32, u32);
+
+/// Chunk containing the tile at `(x, y)`.
+pub fn chunk_of(x: u32, y: u32) -> ChunkCoord {
+    (x / CHUNK_SIZE, y / CHUNK_SIZE)
+}
+
+/// Saved tiles of every chunk that differs from what the seed generates. For
+/// maps loaded without a seed this holds every chunk.
+#[derive(Resource, Default)]
+pub struct ChunkStore {
+    chunks: HashMap<ChunkCoord, HashMap<(u32, u32), TileState>>,
+    /// Soil moisture of stored tiles, where it is known.
+    moisture: HashMap<(u32, u32), f32>,
+}
+
+impl ChunkStore {
+    /// Stores every tile listed in `map`.
+    pub fn insert_map(&mut self, map: &MapFile) {
+        for tile in &map.tiles {
+            self.set(tile.x, tile.y, tile.state());
+            if let Some(moisture) = tile.moisture {
+                self.moisture.insert((tile.x, tile.y), moisture);
+            }
+        }
+    }
+
+    /// Overwrites the saved state of one tile, e.g. when undoing a change to
+    /// a chunk that is not loaded.
+    pub fn set(&mut self, x: u32, y: u32, state: TileState) {
+        self.chunks
+            .entry(chunk_of(x, y))
+            .or_default()
+            .insert((x, y), state);
+    }
+
+    /// Every saved tile with its position.
+    pub fn tiles(&self) -> impl Iterator<Item = ((u32, u32), TileState)> + '_ {
+        self.chunks
+            .values()
+            .flat_map(|chunk| chunk.iter().map(|(pos, state)| (*pos, *state)))
+    }
+
+    pub fn moisture(&self, x: u32, y: u32) -> Option<f32> {
+        self.moisture.get(&(x, y)).copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.moisture.clear();
+    }
+}
+
+struct LoadedChunk {
+    tiles: Vec<Entity>,
+    /// Whether the chunk differs from what the seed generates and so has to
+    /// be saved when it is unloaded.
+    modified: bool,
+}
+
+/// Chunks that are currently spawned.
+#[derive(Resource, Default)]
+pub struct LoadedChunks {
+    chunks: HashMap<ChunkCoord, LoadedChunk>,
+}
+
+impl LoadedChunks {
+    pub fn is_modified(&self, chunk: ChunkCoord) -> bool {
+        self.chunks.get(&chunk).is_some_and(|chunk| chunk.modified)
+    }
+
+    /// Forgets every chunk. The caller despawns their tiles.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
+
+/// Inclusive range of chunk coordinates.
+#[derive(Clone, Copy)]
+struct ChunkRect {
+    min: ChunkCoord,
+    max: ChunkCoord,
+}
+
+impl ChunkRect {
+    /// Chunks overlapping the world-space rectangle from `min` to `max`,
+    /// clamped to the grid.
+    fn covering(grid: &GridConfig, min: Vec2, max: Vec2) -> Self {
+        let origin = grid.tile_translation(0, 0).truncate();
+        let to_chunk = |world: Vec2| {
+            let tile = ((world - origin) / grid.tile_size + 0.5).floor();
+            let x = tile.x.clamp(0.0, (grid.width - 1) as f32) as u32;
+            let y = tile.y.clamp(0.0, (grid.height - 1) as f32) as u32;
+            chunk_of(x, y)
+        };
+        ChunkRect {
+            min: to_chunk(min),
+            max: to_chunk(max),
+        }
+    }
+
+    fn grow(self, grid: &GridConfig, margin: u32) -> Self {
+        let last = chunk_of(grid.width - 1, grid.height - 1);
+        ChunkRect {
+            min: (
+                self.min.0.saturating_sub(margin),
+                self.min.1.saturating_sub(margin),
+            ),
+            max: (
+                (self.max.0 + margin).min(last.0),
+                (self.max.1 + margin).min(last.1),
+            ),
+        }
+    }
+
+    fn contains(&self, chunk: ChunkCoord) -> bool {
+        (self.min.0..=self.max.0).contains(&chunk.0) && (self.min.1..=self.max.1).contains(&chunk.1)
+    }
+}
+
+/// Spawns the tiles of one chunk, restoring its saved state when there is
+/// one and generating it from the seed otherwise.
+fn spawn_chunk(
+    commands: &mut Commands,
+    grid: &GridConfig,
+    seed: Option<u64>,
+    terrain: &TerrainParams,
+    store: &ChunkStore,
+    chunk: ChunkCoord,
+) -> LoadedChunk {
+    let saved = store.chunks.get(&chunk);
+    let mut tiles = Vec::new();
+    for y in chunk.1 * CHUNK_SIZE..((chunk.1 + 1) * CHUNK_SIZE).min(grid.height) {
+        for x in chunk.0 * CHUNK_SIZE..((chunk.0 + 1) * CHUNK_SIZE).min(grid.width) {
+            let state = match (saved.and_then(|saved| saved.get(&(x, y))), seed) {
+                (Some(state), _) => *state,
+                (None, Some(seed)) => TileState {
+                    tile_type: generate_tile(grid, seed, terrain, x, y),
+                    growth: None,
+                },
+                (None, None) => continue,
+            };
+            let tile = spawn_tile(commands, grid, x, y, state.tile_type, state.growth);
+            if let Some(level) = store.moisture(x, y) {
+                commands.entity(tile).insert(Moisture {
+                    level,
+                    ..Default::default()
+                });
+            }
+            tiles.push(tile);
+        }
+    }
+    LoadedChunk {
+        tiles,
+        modified: saved.is_some(),
+    }
+}
+
+/// Spawns every chunk of the map at once, for running without a camera to
+/// stream them in around.
+pub fn spawn_all_chunks_system(
+    mut commands: Commands,
+    grid: Res<GridConfig>,
+    seed: Res<MapSeed>,
+    terrain: Res<TerrainParams>,
+    store: Res<ChunkStore>,
+) {
+    for cy in 0..grid.height.div_ceil(CHUNK_SIZE) {
+        for cx in 0..grid.width.div_ceil(CHUNK_SIZE) {
+            spawn_chunk(&mut commands, &grid, seed.0, &terrain, &store, (cx, cy));
+        }
+    }
+}
+
+/// Flags loaded chunks whose tiles were painted or harvested since they were
+/// spawned. Crops growing on their own do not count: an untouched chunk comes
+/// back from the seed, so storing it would only make saves grow with the
+/// area explored.
+pub fn chunk_modified_system(
+    tiles: Query<(&TilePosition, Ref<TileType>)>,
+    mut loaded: ResMut<LoadedChunks>,
+) {
+    for (pos, tile_type) in &tiles {
+        if !tile_type.is_changed() || tile_type.is_added() {
+            continue;
+        }
+        if let Some(chunk) = loaded.chunks.get_mut(&chunk_of(pos.x, pos.y)) {
+            chunk.modified = true;
+        }
+    }
+}
+
+/// Spawns the chunks around the camera and despawns those that fell far
+/// out of view, saving the modified ones first so they come back unchanged.
+pub fn chunk_streaming_system(
+    mut commands: Commands,
+    grid: Res<GridConfig>,
+    seed: Res<MapSeed>,
+    terrain: Res<TerrainParams>,
+    windows: Query<&Window>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    tiles: Query<(&TilePosition, &TileType, Option<&CropGrowth>, &Moisture)>,
+    mut loaded: ResMut<LoadedChunks>,
+    mut store: ResMut<ChunkStore>,
+) {
+    let window = windows.single();
+    let (camera, projection) = cameras.single();
+    let centre = camera.translation.truncate();
+    let half_view = Vec2::new(window.width(), window.height()) / 2.0 * projection.scale;
+    let view = ChunkRect::covering(&grid, centre - half_view, centre + half_view);
+
+    let keep = view.grow(&grid, UNLOAD_MARGIN);
+    let leaving: Vec<ChunkCoord> = loaded
+        .chunks
+        .keys()
+        .filter(|chunk| !keep.contains(**chunk))
+        .copied()
+        .collect();
+    for coord in leaving {
+        let Some(chunk) = loaded.chunks.remove(&coord) else {
+            continue;
+        };
+        if chunk.modified {
+            let mut saved = HashMap::new();
+            for (pos, tile_type, growth, moisture) in tiles.iter_many(&chunk.tiles) {
+                let state = TileState {
+                    tile_type: *tile_type,
+                    growth: growth.copied(),
+                };
+                saved.insert((pos.x, pos.y), state);
+                store.moisture.insert((pos.x, pos.y), moisture.level);
+            }
+            store.chunks.insert(coord, saved);
+        }
+        for entity in chunk.tiles {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let want = view.grow(&grid, LOAD_MARGIN);
+    for y in want.min.1..=want.max.1 {
+        for x in want.min.0..=want.max.0 {
+            if loaded.chunks.contains_key(&(x, y)) {
+                continue;
+            }
+            let chunk = spawn_chunk(&mut commands, &grid, seed.0, &terrain, &store, (x, y));
+            loaded.chunks.insert((x, y), chunk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chinampa::Fertility;
+    use crate::crops::{GrowthStage, crop_growth_system};
+
+    #[test]
+    fn growing_crops_leave_their_chunk_unmodified() {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<LoadedChunks>()
+            .add_systems(Update, (crop_growth_system, chunk_modified_system).chain());
+        let crop = app
+            .world
+            .spawn((
+                TilePosition { x: 3, y: 4 },
+                TileType::Crop,
+                CropGrowth::new(GrowthStage::Seeded, TileType::Dirt),
+                Moisture {
+                    level: 1.0,
+                    baseline: 1.0,
+                },
+                Fertility(1.0),
+            ))
+            .id();
+        app.world.resource_mut::<LoadedChunks>().chunks.insert(
+            chunk_of(3, 4),
+            LoadedChunk {
+                tiles: vec![crop],
+                modified: false,
+            },
+        );
+
+        for _ in 0..20 {
+            app.world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs(1));
+            app.update();
+        }
+        let growth = app.world.get::<CropGrowth>(crop).unwrap();
+        assert_ne!(growth.stage, GrowthStage::Seeded);
+        assert!(
+            !app.world
+                .resource::<LoadedChunks>()
+                .is_modified(chunk_of(3, 4))
+        );
+
+        *app.world.get_mut::<TileType>(crop).unwrap() = TileType::Dirt;
+        app.update();
+        assert!(
+            app.world
+                .resource::<LoadedChunks>()
+                .is_modified(chunk_of(3, 4))
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::path::PathBuf;
+
+use crate::TileType;
+use crate::export::{DEFAULT_PNG_SCALE, PngOptions};
+use crate::generation::TerrainParams;
+use crate::grid::{GridConfig, MAX_GRID_SIZE};
+use crate::history::DEFAULT_HISTORY_DEPTH;
+
+/// Ticks `simulate` runs for when `--ticks` is not given.
+const DEFAULT_TICKS: u32 = 600;
+/// Simulated seconds per tick when `--tick-seconds` is not given.
+const DEFAULT_TICK_SECONDS: f32 = 0.1;
+/// Largest `--scale`, so exported images stay a sensible size.
+const MAX_PNG_SCALE: u32 = 64;
+
+/// What to do, chosen by the first argument. Without one the editor opens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    #[default]
+    Edit,
+    /// Advance the simulation without a window and print a summary.
+    Simulate,
+    /// Generate a map from the seed and write it to `--output`.
+    Generate,
+    /// Print a summary of a map without simulating it.
+    Stats,
+    /// Render a map to the PNG image given by `--output`.
+    ExportPng,
+}
+
+/// Options accepted on the command line.
+#[derive(Debug)]
+pub struct CliArgs {
+    pub command: Command,
+    /// Map file to load instead of rolling a random grid.
+    pub map: Option<PathBuf>,
+    /// Number of paint strokes that can be undone.
+    pub history_depth: usize,
+    /// JSON file with default grid dimensions.
+    pub config: Option<PathBuf>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub tile_size: Option<f32>,
+    /// Seed for map generation; a random one is picked when absent.
+    pub seed: Option<u64>,
+    /// Terrain generator settings from `--water-level`, `--roughness`,
+    /// `--crop-density` and `--feature-size`.
+    pub terrain: TerrainParams,
+    /// Tileset manifest describing the sprite sheet tiles are drawn from.
+    pub tileset: Option<PathBuf>,
+    /// Key bindings file, in place of the one in the user's config directory.
+    pub bindings: Option<PathBuf>,
+    /// Tile type the erase action resets tiles to.
+    pub erase_type: TileType,
+    /// Number of ticks `simulate` advances.
+    pub ticks: u32,
+    /// Simulated time per tick, in seconds.
+    pub tick_seconds: f32,
+    /// Where the headless commands write the resulting map.
+    pub output: Option<PathBuf>,
+    /// Pixels per tile in exported and imported images.
+    pub scale: Option<u32>,
+    /// Draw grid lines in exported images.
+    pub grid_lines: bool,
+    /// Image to build the map from instead of `--map`.
+    pub image: Option<PathBuf>,
+    /// Colours `--image` pixels stand for, in place of the tile colours.
+    pub palette: Option<PathBuf>,
+    /// Tile types the tiles of Tiled maps stand for, in place of the
+    /// default table.
+    pub tiled_table: Option<PathBuf>,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        CliArgs {
+            command: Command::Edit,
+            map: None,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+            config: None,
+            width: None,
+            height: None,
+            tile_size: None,
+            seed: None,
+            terrain: TerrainParams::default(),
+            tileset: None,
+            bindings: None,
+            erase_type: TileType::Grass,
+            ticks: DEFAULT_TICKS,
+            tick_seconds: DEFAULT_TICK_SECONDS,
+            output: None,
+            scale: None,
+            grid_lines: false,
+            image: None,
+            palette: None,
+            tiled_table: None,
+        }
+    }
+}
+
+impl CliArgs {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut parsed = CliArgs::default();
+        let mut args = args.into_iter().peekable();
+        if let Some(command) = args.next_if(|arg| !arg.starts_with('-')) {
+            parsed.command = match command.as_str() {
+                "simulate" => Command::Simulate,
+                "generate" => Command::Generate,
+                "stats" => Command::Stats,
+                "export-png" => Command::ExportPng,
+                other => return Err(format!("unknown command `{other}`")),
+            };
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--map" => {
+                    let path = args.next().ok_or("--map expects a path")?;
+                    parsed.map = Some(PathBuf::from(path));
+                }
+                "--history-depth" => {
+                    let depth = args.next().ok_or("--history-depth expects a number")?;
+                    parsed.history_depth = depth
+                        .parse()
+                        .map_err(|_| format!("invalid history depth `{depth}`"))?;
+                }
+                "--config" => {
+                    let path = args.next().ok_or("--config expects a path")?;
+                    parsed.config = Some(PathBuf::from(path));
+                }
+                "--tileset" => {
+                    let path = args.next().ok_or("--tileset expects a path")?;
+                    parsed.tileset = Some(PathBuf::from(path));
+                }
+                "--bindings" => {
+                    let path = args.next().ok_or("--bindings expects a path")?;
+                    parsed.bindings = Some(PathBuf::from(path));
+                }
+                "--erase-type" => parsed.erase_type = parse_value(&arg, args.next())?,
+                "--output" => {
+                    let path = args.next().ok_or("--output expects a path")?;
+                    parsed.output = Some(PathBuf::from(path));
+                }
+                "--image" => {
+                    let path = args.next().ok_or("--image expects a path")?;
+                    parsed.image = Some(PathBuf::from(path));
+                }
+                "--palette" => {
+                    let path = args.next().ok_or("--palette expects a path")?;
+                    parsed.palette = Some(PathBuf::from(path));
+                }
+                "--tiled-table" => {
+                    let path = args.next().ok_or("--tiled-table expects a path")?;
+                    parsed.tiled_table = Some(PathBuf::from(path));
+                }
+                "--scale" => {
+                    let scale = parse_value(&arg, args.next())?;
+                    if !(1..=MAX_PNG_SCALE).contains(&scale) {
+                        return Err(format!(
+                            "--scale must be between 1 and {MAX_PNG_SCALE}, got {scale}"
+                        ));
+                    }
+                    parsed.scale = Some(scale);
+                }
+                "--grid-lines" => parsed.grid_lines = true,
+                "--ticks" => parsed.ticks = parse_value(&arg, args.next())?,
+                "--tick-seconds" => {
+                    parsed.tick_seconds = parse_value(&arg, args.next())?;
+                    if !parsed.tick_seconds.is_finite() || parsed.tick_seconds <= 0.0 {
+                        return Err(format!(
+                            "--tick-seconds must be positive, got {}",
+                            parsed.tick_seconds
+                        ));
+                    }
+                }
+                "--width" => parsed.width = Some(parse_value(&arg, args.next())?),
+                "--height" => parsed.height = Some(parse_value(&arg, args.next())?),
+                "--tile-size" => parsed.tile_size = Some(parse_value(&arg, args.next())?),
+                "--seed" => parsed.seed = Some(parse_value(&arg, args.next())?),
+                "--water-level" => parsed.terrain.water_level = parse_fraction(&arg, args.next())?,
+                "--roughness" => parsed.terrain.roughness = parse_fraction(&arg, args.next())?,
+                "--crop-density" => {
+                    parsed.terrain.crop_density = parse_fraction(&arg, args.next())?
+                }
+                "--feature-size" => parsed.terrain.feature_size = parse_value(&arg, args.next())?,
+                other => return Err(format!("unknown argument `{other}`")),
+            }
+        }
+
+        if parsed.command == Command::ExportPng && parsed.output.is_none() {
+            return Err("export-png expects --output".to_string());
+        }
+        if parsed.map.is_some() && parsed.image.is_some() {
+            return Err("--map and --image cannot be used together".to_string());
+        }
+        if parsed.command == Command::Generate {
+            if parsed.map.is_some() || parsed.image.is_some() {
+                return Err(
+                    "generate builds a new map and does not take --map or --image".to_string(),
+                );
+            }
+            if parsed.output.is_none() {
+                return Err("generate expects --output".to_string());
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Options for rendering maps to PNG.
+    pub fn png_options(&self) -> PngOptions {
+        PngOptions {
+            scale: self.scale.unwrap_or(DEFAULT_PNG_SCALE),
+            grid_lines: self.grid_lines,
+        }
+    }
+
+    /// Grid dimensions from the config file, if any, with command-line
+    /// overrides applied on top.
+    pub fn grid_config(&self) -> Result<GridConfig, String> {
+        let mut grid = match &self.config {
+            Some(path) => GridConfig::load(path)
+                .map_err(|err| format!("could not read {}: {err}", path.display()))?,
+            None => GridConfig::default(),
+        };
+        grid.width = self.width.unwrap_or(grid.width);
+        grid.height = self.height.unwrap_or(grid.height);
+        grid.tile_size = self.tile_size.unwrap_or(grid.tile_size);
+
+        if !(1..=MAX_GRID_SIZE).contains(&grid.width) || !(1..=MAX_GRID_SIZE).contains(&grid.height)
+        {
+            return Err(format!(
+                "grid must be between 1x1 and {MAX_GRID_SIZE}x{MAX_GRID_SIZE}, got {}x{}",
+                grid.width, grid.height
+            ));
+        }
+        if grid.tile_size <= 2.0 {
+            return Err(format!(
+                "tile size must be larger than 2, got {}",
+                grid.tile_size
+            ));
+        }
+        Ok(grid)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{flag} expects a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for {flag}"))
+}
+
+/// Parses a value that must lie in `0.0..=1.0`.
+fn parse_fraction(flag: &str, value: Option<String>) -> Result<f32, String> {
+    let fraction: f32 = parse_value(flag, value)?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!("{flag} must be between 0 and 1, got {fraction}"));
+    }
+    Ok(fraction)
+}
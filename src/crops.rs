@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::TileType;
+use crate::chinampa::Fertility;
+use crate::moisture::Moisture;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GrowthStage {
+    Seeded,
+    Sprouting,
+    Mature,
+    Withered,
+}
+
+impl GrowthStage {
+    /// Seconds spent in this stage before moving on, or `None` for the
+    /// final stage.
+    fn duration(&self) -> Option<f32> {
+        match self {
+            GrowthStage::Seeded => Some(5.0),
+            GrowthStage::Sprouting => Some(10.0),
+            GrowthStage::Mature => Some(20.0),
+            GrowthStage::Withered => None,
+        }
+    }
+
+    fn next(&self) -> GrowthStage {
+        match self {
+            GrowthStage::Seeded => GrowthStage::Sprouting,
+            GrowthStage::Sprouting => GrowthStage::Mature,
+            GrowthStage::Mature | GrowthStage::Withered => GrowthStage::Withered,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            GrowthStage::Seeded => Color::rgb(0.45, 0.35, 0.15),
+            GrowthStage::Sprouting => Color::rgb(0.4, 0.75, 0.3),
+            GrowthStage::Mature => Color::rgb(0.85, 0.65, 0.15),
+            GrowthStage::Withered => Color::rgb(0.4, 0.35, 0.3),
+        }
+    }
+}
+
+/// Seconds a crop survives on soil below [`DRY_THRESHOLD`] before it withers.
+///
+/// [`DRY_THRESHOLD`]: crate::moisture::DRY_THRESHOLD
+const DROUGHT_TOLERANCE: f32 = 15.0;
+
+/// Growth state of a `TileType::Crop` tile.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CropGrowth {
+    pub stage: GrowthStage,
+    /// Tile the crop was planted on, restored when it is harvested.
+    pub bed: TileType,
+    elapsed: f32,
+    /// Seconds spent on soil too dry to grow in.
+    drought: f32,
+}
+
+impl CropGrowth {
+    pub fn new(stage: GrowthStage, bed: TileType) -> Self {
+        CropGrowth {
+            stage,
+            bed,
+            elapsed: 0.0,
+            drought: 0.0,
+        }
+    }
+}
+
+/// Number of mature crops harvested so far.
+#[derive(Resource, Default)]
+pub struct HarvestYield(pub u32);
+
+#[derive(Component)]
+pub struct YieldText;
+
+/// Color a tile is drawn with, taking the crop stage into account.
+pub fn tile_color(tile_type: &TileType, growth: Option<&CropGrowth>) -> Color {
+    match growth {
+        Some(growth) if *tile_type == TileType::Crop => growth.stage.color(),
+        _ => tile_type.color(),
+    }
+}
+
+/// Advances crops through their stages at a speed set by soil moisture and
+/// fertility. Crops left on dry soil for too long wither early.
+pub fn crop_growth_system(
+    time: Res<Time>,
+    mut crops: Query<(&mut CropGrowth, &Moisture, &Fertility)>,
+) {
+    for (mut growth, moisture, fertility) in &mut crops {
+        let Some(duration) = growth.stage.duration() else {
+            continue;
+        };
+
+        let rate = moisture.growth_rate();
+        if rate == 0.0 {
+            growth.drought += time.delta_seconds();
+            if growth.drought >= DROUGHT_TOLERANCE {
+                growth.stage = GrowthStage::Withered;
+            }
+            continue;
+        }
+        growth.drought = 0.0;
+
+        growth.elapsed += time.delta_seconds() * rate * fertility.0;
+        if growth.elapsed >= duration {
+            growth.stage = growth.stage.next();
+            growth.elapsed = 0.0;
+        }
+    }
+}
+
+pub fn yield_text_system(harvest: Res<HarvestYield>, mut texts: Query<&mut Text, With<YieldText>>) {
+    if !harvest.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        text.sections[0].value = format!("Yield: {}", harvest.0);
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use image::{Rgba, RgbaImage};
+
+use crate::chunks::ChunkStore;
+use crate::generation::{MapSeed, TerrainParams, generate_tile};
+use crate::grid::{GridConfig, TypeMap, tile_types};
+use crate::map::MapPath;
+use crate::{Tile, TilePosition, TileType};
+
+/// Pixels per tile when `--scale` is not given.
+pub const DEFAULT_PNG_SCALE: u32 = 8;
+
+/// Largest image written, in pixels: 8192x8192, or 256 MB uncompressed.
+const MAX_PNG_PIXELS: u64 = 8192 * 8192;
+
+const GRID_LINE_COLOR: Rgba<u8> = Rgba([32, 32, 32, 255]);
+
+/// How maps are rendered to PNG, from `--scale` and `--grid-lines`.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PngOptions {
+    /// Width and height of each tile in pixels.
+    pub scale: u32,
+    /// Draw a one pixel line along the top and left of every tile.
+    pub grid_lines: bool,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        PngOptions {
+            scale: DEFAULT_PNG_SCALE,
+            grid_lines: false,
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct ExportPngEvent;
+
+/// Refuses images too large to hold in memory, before any tile is drawn or
+/// generated for them.
+pub fn check_png_size(width: u32, height: u32, options: PngOptions) -> Result<(), String> {
+    let scale = options.scale.max(1) as u64;
+    let pixels = width as u64 * height as u64 * scale * scale;
+    if pixels > MAX_PNG_PIXELS {
+        return Err(format!(
+            "a {width}x{height} map at {scale} pixels per tile makes a {}x{} image, \
+             more than the {MAX_PNG_PIXELS} pixels allowed",
+            width as u64 * scale,
+            height as u64 * scale
+        ));
+    }
+    Ok(())
+}
+
+/// Draws each tile as a square of its flat colour, north up. Tiles missing
+/// from `types` are left transparent. Runs on the CPU, so it works without
+/// a window or GPU.
+pub fn render_png(
+    width: u32,
+    height: u32,
+    types: &TypeMap,
+    options: PngOptions,
+) -> Result<RgbaImage, String> {
+    check_png_size(width, height, options)?;
+    let scale = options.scale.max(1);
+    let mut image = RgbaImage::new(width * scale, height * scale);
+    for ((x, y), tile_type) in types {
+        if *x >= width || *y >= height {
+            continue;
+        }
+        let color = Rgba(tile_type.color().as_rgba_u8());
+        // Image rows run top to bottom, world y bottom to top.
+        let left = x * scale;
+        let top = (height - 1 - y) * scale;
+        for py in top..top + scale {
+            for px in left..left + scale {
+                let on_line = options.grid_lines && scale > 1 && (px == left || py == top);
+                let pixel = if on_line { GRID_LINE_COLOR } else { color };
+                image.put_pixel(px, py, pixel);
+            }
+        }
+    }
+    Ok(image)
+}
+
+pub fn export_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    types: &TypeMap,
+    options: PngOptions,
+) -> Result<(), String> {
+    render_png(width, height, types, options)?
+        .save(path)
+        .map_err(|err| format!("could not write {}: {err}", path.display()))
+}
+
+/// Exports the whole map next to the map file, with the `.png` extension.
+/// Chunks that are not loaded come from the store or the seed, so the image
+/// covers more than what is on screen.
+pub fn export_png_system(
+    mut events: EventReader<ExportPngEvent>,
+    tiles: Query<(&TilePosition, &TileType), With<Tile>>,
+    store: Res<ChunkStore>,
+    grid: Res<GridConfig>,
+    seed: Res<MapSeed>,
+    terrain: Res<TerrainParams>,
+    path: Res<MapPath>,
+    options: Res<PngOptions>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    if let Err(err) = check_png_size(grid.width, grid.height, *options) {
+        error!("failed to export map image: {err}");
+        return;
+    }
+
+    let mut types = TypeMap::new();
+    if let Some(seed) = seed.0 {
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                types.insert((x, y), generate_tile(&grid, seed, &terrain, x, y));
+            }
+        }
+    }
+    types.extend(store.tiles().map(|(pos, state)| (pos, state.tile_type)));
+    types.extend(tile_types(tiles.iter()));
+
+    let png_path = path.0.with_extension("png");
+    match export_png(&png_path, grid.width, grid.height, &types, *options) {
+        Ok(()) => info!("exported map image to {}", png_path.display()),
+        Err(err) => error!("failed to export map image: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_images_over_the_pixel_limit() {
+        let options = PngOptions::default();
+        assert!(check_png_size(1024, 1024, options).is_ok());
+        assert!(check_png_size(1025, 1024, options).is_err());
+
+        let largest = PngOptions {
+            scale: 64,
+            ..options
+        };
+        assert!(check_png_size(16384, 16384, largest).is_err());
+        assert!(render_png(16384, 16384, &TypeMap::new(), options).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use bevy::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::TileType;
+use crate::grid::GridConfig;
+
+/// Farmland only appears within this many tiles of water.
+const FARM_REACH: u32 = 3;
+
+/// Offset mixed into the seed so the farmland noise is independent of the
+/// elevation noise.
+const FARM_NOISE_SALT: u64 = 0x5eed_f4a3;
+
+/// Offset mixed into the seed for deciding which farmland is planted.
+const CROP_SALT: u64 = 0xc409_5eed;
+
+/// Seed the untouched parts of the current map are generated from, or `None`
+/// for maps loaded from a file that lists every tile.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapSeed(pub Option<u64>);
+
+/// Session RNG, seeded from `--seed`, that hands out seeds for new maps so a
+/// whole editing session can be replayed.
+#[derive(Resource)]
+pub struct GardenRng(pub ChaCha8Rng);
+
+impl GardenRng {
+    pub fn new(seed: u64) -> Self {
+        GardenRng(ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    pub fn next_seed(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+}
+
+#[derive(Component)]
+pub struct SeedText;
+
+/// Knobs for the terrain generator. Saved with seeded maps so the parts of
+/// the world that were never edited come out the same when reloaded.
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct TerrainParams {
+    /// Elevation below which tiles become water, in `0.0..=1.0`. Higher
+    /// values give bigger lakes.
+    pub water_level: f32,
+    /// How much each finer octave of noise contributes, in `0.0..=1.0`. Low
+    /// values give smooth shores, high values ragged ones.
+    pub roughness: f32,
+    /// Chance that a farmland tile near water is already planted.
+    pub crop_density: f32,
+    /// Approximate size of terrain features, in tiles.
+    pub feature_size: f32,
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        TerrainParams {
+            water_level: 0.35,
+            roughness: 0.5,
+            crop_density: 0.3,
+            feature_size: 8.0,
+        }
+    }
+}
+
+/// Hashes a lattice point to a value in `0.0..1.0`.
+fn lattice_value(seed: u64, x: i64, y: i64) -> f32 {
+    let mut hash = seed
+        ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^= hash >> 33;
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Smoothly interpolated value noise at `(x, y)`, in `0.0..1.0`.
+fn value_noise(seed: u64, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+    let (ix, iy) = (x0 as i64, y0 as i64);
+
+    let top = lattice_value(seed, ix, iy) * (1.0 - tx) + lattice_value(seed, ix + 1, iy) * tx;
+    let bottom =
+        lattice_value(seed, ix, iy + 1) * (1.0 - tx) + lattice_value(seed, ix + 1, iy + 1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// Four octaves of value noise, each at double the frequency of the last and
+/// weighted by `roughness`, normalised back to `0.0..1.0`.
+fn fractal_noise(seed: u64, x: f32, y: f32, roughness: f32) -> f32 {
+    let mut total = 0.0;
+    let mut weight = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for octave in 0..4 {
+        total += value_noise(seed.wrapping_add(octave), x * frequency, y * frequency) * amplitude;
+        weight += amplitude;
+        amplitude *= roughness;
+        frequency *= 2.0;
+    }
+    total / weight
+}
+
+/// Whether the elevation at `(x, y)` lies below the water level.
+fn is_water(seed: u64, params: &TerrainParams, x: u32, y: u32) -> bool {
+    let scale = params.feature_size.max(1.0);
+    fractal_noise(seed, x as f32 / scale, y as f32 / scale, params.roughness) < params.water_level
+}
+
+/// Type of the tile at `(x, y)` in the world generated from `seed`. Every
+/// tile depends only on the seed, the parameters and its own neighbourhood,
+/// so chunks can be generated independently and always come out the same.
+///
+/// Elevation noise below the water level forms lakes; dirt and crop patches
+/// follow a second noise field but only close to water, and everything else
+/// is grass.
+pub fn generate_tile(
+    grid: &GridConfig,
+    seed: u64,
+    params: &TerrainParams,
+    x: u32,
+    y: u32,
+) -> TileType {
+    if is_water(seed, params, x, y) {
+        return TileType::Water;
+    }
+
+    let reach = FARM_REACH as i32;
+    let near_water = (-reach..=reach).any(|dy| {
+        let span = reach - dy.abs();
+        (-span..=span).any(|dx| {
+            let (Some(nx), Some(ny)) = (x.checked_add_signed(dx), y.checked_add_signed(dy)) else {
+                return false;
+            };
+            grid.contains(nx, ny) && is_water(seed, params, nx, ny)
+        })
+    });
+    if !near_water {
+        return TileType::Grass;
+    }
+
+    let scale = params.feature_size.max(1.0) / 2.0;
+    let farm_seed = seed ^ FARM_NOISE_SALT;
+    if fractal_noise(farm_seed, x as f32 / scale, y as f32 / scale, 0.5) <= 0.5 {
+        TileType::Grass
+    } else if lattice_value(seed ^ CROP_SALT, x as i64, y as i64) < params.crop_density {
+        TileType::Crop
+    } else {
+        TileType::Dirt
+    }
+}
+
+pub fn seed_text_system(seed: Res<MapSeed>, mut texts: Query<&mut Text, With<SeedText>>) {
+    if !seed.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        text.sections[0].value = match seed.0 {
+            Some(seed) => format!("Seed: {seed}"),
+            None => "Seed: -".to_string(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_types(grid: &GridConfig, seed: u64, params: &TerrainParams) -> Vec<TileType> {
+        (0..grid.height)
+            .flat_map(|y| (0..grid.width).map(move |x| (x, y)))
+            .map(|(x, y)| generate_tile(grid, seed, params, x, y))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_produces_same_grid() {
+        let grid = GridConfig::default();
+        let params = TerrainParams::default();
+        assert_eq!(
+            generate_types(&grid, 42, &params),
+            generate_types(&grid, 42, &params)
+        );
+    }
+
+    #[test]
+    fn different_seeds_produce_different_grids() {
+        let grid = GridConfig::default();
+        let params = TerrainParams::default();
+        assert_ne!(
+            generate_types(&grid, 1, &params),
+            generate_types(&grid, 2, &params)
+        );
+    }
+
+    #[test]
+    fn water_level_controls_how_much_water_there_is() {
+        let grid = GridConfig::default();
+        let dry = TerrainParams {
+            water_level: 0.0,
+            ..Default::default()
+        };
+        let flooded = TerrainParams {
+            water_level: 1.0,
+            ..Default::default()
+        };
+        assert!(!generate_types(&grid, 3, &dry).contains(&TileType::Water));
+        assert!(
+            generate_types(&grid, 3, &flooded)
+                .iter()
+                .all(|tile_type| *tile_type == TileType::Water)
+        );
+    }
+
+    #[test]
+    fn session_rng_hands_out_reproducible_seeds() {
+        let mut first = GardenRng::new(7);
+        let mut second = GardenRng::new(7);
+        let seeds: Vec<u64> = (0..3).map(|_| first.next_seed()).collect();
+        assert_eq!(
+            seeds,
+            (0..3).map(|_| second.next_seed()).collect::<Vec<_>>()
+        );
+    }
+}
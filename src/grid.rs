@@ -0,0 +1,122 @@
+use std::collections::{HashMap, HashSet};
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{Tile, TilePosition, TileType};
+
+pub const DEFAULT_TILE_SIZE: f32 = 32.0;
+pub const DEFAULT_GRID_WIDTH: u32 = 10;
+pub const DEFAULT_GRID_HEIGHT: u32 = 10;
+
+/// Largest width or height the editor accepts for a grid. Only the chunks
+/// around the camera are ever spawned, so this is bounded by coordinate
+/// precision rather than entity count.
+pub const MAX_GRID_SIZE: u32 = 16384;
+
+/// Dimensions of the garden and the size tiles are drawn at.
+///
+/// Loaded from `--config`, overridden by `--width`, `--height` and
+/// `--tile-size`, and replaced whenever a map of a different size is loaded.
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct GridConfig {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: f32,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        GridConfig {
+            width: DEFAULT_GRID_WIDTH,
+            height: DEFAULT_GRID_HEIGHT,
+            tile_size: DEFAULT_TILE_SIZE,
+        }
+    }
+}
+
+impl GridConfig {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        serde_json::from_str(&contents).map_err(|err| err.to_string())
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// World-space centre of the tile at `(x, y)`.
+    pub fn tile_translation(&self, x: u32, y: u32) -> Vec3 {
+        let pos_x = x as f32 * self.tile_size - (self.width as f32 * self.tile_size / 2.0);
+        let pos_y = y as f32 * self.tile_size - (self.height as f32 * self.tile_size / 2.0);
+        Vec3::new(pos_x, pos_y, 0.0)
+    }
+
+    /// Tile containing the world-space point `world`, if it lies on the grid.
+    pub fn world_to_tile(&self, world: Vec2) -> Option<(u32, u32)> {
+        let x =
+            ((world.x + self.width as f32 * self.tile_size / 2.0) / self.tile_size + 0.5).floor();
+        let y =
+            ((world.y + self.height as f32 * self.tile_size / 2.0) / self.tile_size + 0.5).floor();
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (x, y) = (x as u32, y as u32);
+        self.contains(x, y).then_some((x, y))
+    }
+
+    /// Positions orthogonally adjacent to `(x, y)` that lie inside the grid.
+    pub fn neighbors(&self, x: u32, y: u32) -> impl Iterator<Item = (u32, u32)> {
+        [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ]
+        .into_iter()
+        .filter(|(x, y)| self.contains(*x, *y))
+    }
+}
+
+/// Types of the spawned tiles by position. Tiles in chunks that are not
+/// loaded are absent.
+pub type TypeMap = HashMap<(u32, u32), TileType>;
+
+/// Snapshot of the types of every spawned tile.
+pub fn tile_types<'a>(tiles: impl Iterator<Item = (&'a TilePosition, &'a TileType)>) -> TypeMap {
+    tiles
+        .map(|(pos, tile_type)| ((pos.x, pos.y), *tile_type))
+        .collect()
+}
+
+/// Tile entities by grid position, so systems can go straight from a
+/// coordinate to its entity instead of scanning every tile.
+#[derive(Resource, Default)]
+pub struct TileGrid {
+    tiles: HashMap<(u32, u32), Entity>,
+}
+
+impl TileGrid {
+    pub fn get(&self, x: u32, y: u32) -> Option<Entity> {
+        self.tiles.get(&(x, y)).copied()
+    }
+}
+
+/// Keeps [`TileGrid`] in step with tiles as they are spawned and despawned.
+pub fn tile_grid_system(
+    added: Query<(Entity, &TilePosition), Added<Tile>>,
+    mut removed: RemovedComponents<Tile>,
+    mut tile_grid: ResMut<TileGrid>,
+) {
+    let removed: HashSet<Entity> = removed.read().collect();
+    if !removed.is_empty() {
+        tile_grid
+            .tiles
+            .retain(|_, entity| !removed.contains(entity));
+    }
+    for (entity, pos) in &added {
+        tile_grid.tiles.insert((pos.x, pos.y), entity);
+    }
+}
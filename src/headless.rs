@@ -0,0 +1,232 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+
+use crate::chinampa::{Fertility, chinampa_fertility_system};
+use crate::chunks::{ChunkStore, spawn_all_chunks_system};
+use crate::cli::{CliArgs, Command};
+use crate::crops::{CropGrowth, GrowthStage, crop_growth_system};
+use crate::export::{check_png_size, export_png};
+use crate::generation::{MapSeed, TerrainParams};
+use crate::grid::{GridConfig, TileGrid, tile_grid_system, tile_types};
+use crate::history::TileState;
+use crate::map::{MAP_FORMAT_VERSION, MapFile, MapTile, save_map};
+use crate::moisture::{
+    DRY_THRESHOLD, Moisture, RainEvent, RainTimer, moisture_decay_system, rain_system,
+    rain_timer_system, recompute_moisture_system,
+};
+use crate::tiled::TiledTable;
+use crate::{Tile, TilePosition, TileType};
+
+/// Runs `simulate`, `generate`, `stats` or `export-png`: builds the garden
+/// without a window, advances it and prints a summary, or the image for
+/// `export-png`, writing the result to `--output` when one is given.
+pub fn run(
+    args: &CliArgs,
+    grid: GridConfig,
+    terrain: TerrainParams,
+    store: ChunkStore,
+    seed: Option<u64>,
+    table: TiledTable,
+) -> Result<(), String> {
+    let ticks = match args.command {
+        Command::Simulate => args.ticks,
+        Command::Generate | Command::Stats | Command::ExportPng => 0,
+        Command::Edit => unreachable!("the editor needs a window"),
+    };
+    if args.command == Command::ExportPng {
+        check_png_size(grid.width, grid.height, args.png_options())?;
+    }
+
+    let mut app = headless_app(grid, terrain, store, seed, args.tick_seconds);
+    app.insert_resource(table);
+    // The first update only spawns the map and settles moisture and
+    // fertility; time starts advancing with the next one.
+    for _ in 0..=ticks {
+        app.update();
+    }
+
+    if args.command == Command::ExportPng {
+        let path = args
+            .output
+            .as_deref()
+            .expect("export-png requires --output");
+        let grid = *app.world.resource::<GridConfig>();
+        let types = tile_types(
+            app.world
+                .query_filtered::<(&TilePosition, &TileType), With<Tile>>()
+                .iter(&app.world),
+        );
+        export_png(path, grid.width, grid.height, &types, args.png_options())?;
+        println!("wrote {}", path.display());
+        return Ok(());
+    }
+
+    println!("{}", GardenSummary::collect(&mut app.world));
+    if let Some(path) = &args.output {
+        write_map(&mut app.world, path)?;
+        println!("wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// The simulation systems on [`MinimalPlugins`], with every chunk spawned
+/// up front and each update advancing time by `tick_seconds`.
+fn headless_app(
+    grid: GridConfig,
+    terrain: TerrainParams,
+    store: ChunkStore,
+    seed: Option<u64>,
+    tick_seconds: f32,
+) -> App {
+    let tick = Duration::from_secs_f32(tick_seconds);
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(TimeUpdateStrategy::ManualDuration(tick))
+        .insert_resource(grid)
+        .insert_resource(terrain)
+        .insert_resource(store)
+        .insert_resource(MapSeed(seed))
+        .init_resource::<TileGrid>()
+        .init_resource::<RainTimer>()
+        .add_event::<RainEvent>()
+        .add_systems(Startup, spawn_all_chunks_system)
+        .add_systems(
+            Update,
+            (
+                tile_grid_system,
+                recompute_moisture_system,
+                chinampa_fertility_system,
+                rain_timer_system,
+                rain_system,
+                moisture_decay_system,
+                crop_growth_system,
+            )
+                .chain(),
+        );
+    // Long ticks would otherwise be clamped like a lagging frame.
+    app.world
+        .resource_mut::<Time<Virtual>>()
+        .set_max_delta(tick.max(Duration::from_millis(250)));
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// Writes every tile, so the map loads the same wherever generation
+/// changes in later versions.
+fn write_map(world: &mut World, path: &Path) -> Result<(), String> {
+    let grid = *world.resource::<GridConfig>();
+    let seed = world.resource::<MapSeed>().0;
+    let terrain = *world.resource::<TerrainParams>();
+    let table = world.resource::<TiledTable>().clone();
+    let mut tiles: Vec<MapTile> = world
+        .query_filtered::<(&TilePosition, &TileType, Option<&CropGrowth>, &Moisture), With<Tile>>()
+        .iter(world)
+        .map(|(pos, tile_type, growth, moisture)| {
+            let state = TileState {
+                tile_type: *tile_type,
+                growth: growth.copied(),
+            };
+            MapTile::new(pos.x, pos.y, state).with_moisture(Some(moisture.level))
+        })
+        .collect();
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+
+    let map = MapFile {
+        version: MAP_FORMAT_VERSION,
+        width: grid.width,
+        height: grid.height,
+        seed,
+        terrain: seed.map(|_| terrain),
+        tiles,
+    };
+    save_map(path, &map, &table).map_err(|err| format!("could not write {}: {err}", path.display()))
+}
+
+/// Totals printed by the headless commands.
+struct GardenSummary {
+    width: u32,
+    height: u32,
+    seconds: f32,
+    tiles: Vec<(TileType, usize)>,
+    crops: Vec<(GrowthStage, usize)>,
+    mean_moisture: f32,
+    dry_tiles: usize,
+    ready_yield: u32,
+}
+
+impl GardenSummary {
+    fn collect(world: &mut World) -> Self {
+        const STAGES: [GrowthStage; 4] = [
+            GrowthStage::Seeded,
+            GrowthStage::Sprouting,
+            GrowthStage::Mature,
+            GrowthStage::Withered,
+        ];
+        let grid = world.resource::<GridConfig>();
+        let mut summary = GardenSummary {
+            width: grid.width,
+            height: grid.height,
+            seconds: world.resource::<Time>().elapsed_seconds(),
+            tiles: TileType::ALL.into_iter().map(|t| (t, 0)).collect(),
+            crops: STAGES.into_iter().map(|stage| (stage, 0)).collect(),
+            mean_moisture: 0.0,
+            dry_tiles: 0,
+            ready_yield: 0,
+        };
+
+        let mut tile_count = 0;
+        let mut total_moisture = 0.0;
+        let mut tiles = world.query::<(&TileType, Option<&CropGrowth>, &Moisture, &Fertility)>();
+        for (tile_type, growth, moisture, fertility) in tiles.iter(world) {
+            tile_count += 1;
+            total_moisture += moisture.level;
+            if moisture.level < DRY_THRESHOLD {
+                summary.dry_tiles += 1;
+            }
+            if let Some(entry) = summary.tiles.iter_mut().find(|(t, _)| t == tile_type) {
+                entry.1 += 1;
+            }
+            if let Some(growth) = growth {
+                if let Some(entry) = summary.crops.iter_mut().find(|(s, _)| *s == growth.stage) {
+                    entry.1 += 1;
+                }
+                if growth.stage == GrowthStage::Mature {
+                    summary.ready_yield += fertility.harvest_yield();
+                }
+            }
+        }
+        if tile_count > 0 {
+            summary.mean_moisture = total_moisture / tile_count as f32;
+        }
+        summary
+    }
+}
+
+impl fmt::Display for GardenSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "garden {}x{} after {:.1}s",
+            self.width, self.height, self.seconds
+        )?;
+        writeln!(f, "tiles: {}", counts(&self.tiles))?;
+        writeln!(f, "crops: {}", counts(&self.crops))?;
+        writeln!(
+            f,
+            "moisture: mean {:.2}, {} tiles too dry for crops",
+            self.mean_moisture, self.dry_tiles
+        )?;
+        write!(f, "ready to harvest: yield {}", self.ready_yield)
+    }
+}
+
+/// Formats counts as `Grass 12, Dirt 3`.
+fn counts<T: fmt::Debug>(counts: &[(T, usize)]) -> String {
+    let counts: Vec<String> = counts.iter().map(|(t, n)| format!("{t:?} {n}")).collect();
+    counts.join(", ")
+}
@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::actions::{Action, ActionEvent, ActionState};
+use crate::chunks::ChunkStore;
+use crate::crops::{CropGrowth, HarvestYield};
+use crate::grid::TileGrid;
+use crate::{TileType, apply_tile_state};
+
+/// Number of strokes kept when no `--history-depth` is given.
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+/// Everything painting can change about a single tile.
+#[derive(Clone, Copy, Debug)]
+pub struct TileState {
+    pub tile_type: TileType,
+    pub growth: Option<CropGrowth>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TileChange {
+    pub x: u32,
+    pub y: u32,
+    pub before: TileState,
+    pub after: TileState,
+}
+
+/// One undoable entry: every tile touched between pressing and releasing the
+/// mouse button.
+#[derive(Debug, Default)]
+pub struct PaintCommand {
+    changes: Vec<TileChange>,
+    /// Yield gained by harvesting in this entry, taken back on undo.
+    harvested: u32,
+}
+
+/// Undo and redo stacks for tile painting.
+#[derive(Resource)]
+pub struct PaintHistory {
+    undo: VecDeque<PaintCommand>,
+    redo: Vec<PaintCommand>,
+    stroke: PaintCommand,
+    depth: usize,
+}
+
+impl PaintHistory {
+    pub fn new(depth: usize) -> Self {
+        PaintHistory {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            stroke: PaintCommand::default(),
+            depth,
+        }
+    }
+
+    /// Adds a change to the stroke in progress.
+    pub fn record(&mut self, change: TileChange) {
+        self.stroke.changes.push(change);
+    }
+
+    /// Adds a harvested crop to the stroke in progress, with the yield it
+    /// gave.
+    pub fn record_harvest(&mut self, change: TileChange, tile_yield: u32) {
+        self.stroke.changes.push(change);
+        self.stroke.harvested += tile_yield;
+    }
+
+    /// Closes the stroke in progress and makes it the newest undo entry.
+    pub fn finish_stroke(&mut self) {
+        if self.stroke.changes.is_empty() {
+            return;
+        }
+        self.undo.push_back(std::mem::take(&mut self.stroke));
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    fn undo(&mut self) -> Option<&PaintCommand> {
+        let command = self.undo.pop_back()?;
+        self.redo.push(command);
+        self.redo.last()
+    }
+
+    fn redo(&mut self) -> Option<&PaintCommand> {
+        let command = self.redo.pop()?;
+        self.undo.push_back(command);
+        self.undo.back()
+    }
+
+    /// Forgets everything, e.g. after the tiles were replaced by a loaded map.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.stroke = PaintCommand::default();
+    }
+}
+
+pub fn finish_stroke_system(state: Res<ActionState>, mut history: ResMut<PaintHistory>) {
+    if state.just_released(Action::Paint) {
+        history.finish_stroke();
+    }
+}
+
+/// Steps back through the history on [`Action::Undo`] and forward again on
+/// [`Action::Redo`].
+pub fn undo_redo_system(
+    mut commands: Commands,
+    mut events: EventReader<ActionEvent>,
+    mut history: ResMut<PaintHistory>,
+    tile_grid: Res<TileGrid>,
+    mut store: ResMut<ChunkStore>,
+    mut harvest: ResMut<HarvestYield>,
+    mut tiles: Query<&mut TileType>,
+) {
+    let mut states: Vec<(u32, u32, TileState)> = Vec::new();
+    for ActionEvent(action) in events.read() {
+        match action {
+            Action::Undo => {
+                if let Some(command) = history.undo() {
+                    harvest.0 = harvest.0.saturating_sub(command.harvested);
+                    states.extend(
+                        command
+                            .changes
+                            .iter()
+                            .rev()
+                            .map(|change| (change.x, change.y, change.before)),
+                    );
+                }
+            }
+            Action::Redo => {
+                if let Some(command) = history.redo() {
+                    harvest.0 += command.harvested;
+                    states.extend(
+                        command
+                            .changes
+                            .iter()
+                            .map(|change| (change.x, change.y, change.after)),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Tiles in chunks that have since been unloaded are changed in their
+    // saved copy instead.
+    for (x, y, state) in states {
+        let loaded = tile_grid
+            .get(x, y)
+            .and_then(|entity| Some((entity, tiles.get_mut(entity).ok()?)));
+        match loaded {
+            Some((entity, mut tile_type)) => {
+                apply_tile_state(&mut commands, entity, &mut tile_type, state);
+            }
+            None => store.set(x, y, state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(x: u32, from: TileType, to: TileType) -> TileChange {
+        let state = |tile_type| TileState {
+            tile_type,
+            growth: None,
+        };
+        TileChange {
+            x,
+            y: 0,
+            before: state(from),
+            after: state(to),
+        }
+    }
+
+    fn xs(command: Option<&PaintCommand>) -> Vec<u32> {
+        command.map_or_else(Vec::new, |command| {
+            command.changes.iter().map(|change| change.x).collect()
+        })
+    }
+
+    #[test]
+    fn a_stroke_is_one_entry() {
+        let mut history = PaintHistory::new(10);
+        history.record(change(0, TileType::Grass, TileType::Dirt));
+        history.record(change(1, TileType::Grass, TileType::Dirt));
+        history.finish_stroke();
+        // Releasing without painting adds nothing.
+        history.finish_stroke();
+
+        assert_eq!(xs(history.undo()), vec![0, 1]);
+        assert!(history.undo().is_none());
+        assert_eq!(xs(history.redo()), vec![0, 1]);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn keeps_only_the_newest_strokes() {
+        let mut history = PaintHistory::new(2);
+        for x in 0..4 {
+            history.record(change(x, TileType::Grass, TileType::Water));
+            history.finish_stroke();
+        }
+        assert_eq!(xs(history.undo()), vec![3]);
+        assert_eq!(xs(history.undo()), vec![2]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn a_new_stroke_clears_redo() {
+        let mut history = PaintHistory::new(10);
+        history.record(change(0, TileType::Grass, TileType::Dirt));
+        history.finish_stroke();
+        assert!(history.undo().is_some());
+
+        history.record(change(1, TileType::Grass, TileType::Water));
+        history.finish_stroke();
+        assert!(history.redo().is_none());
+        assert_eq!(xs(history.undo()), vec![1]);
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn harvests_carry_their_yield() {
+        let mut history = PaintHistory::new(10);
+        history.record_harvest(change(0, TileType::Crop, TileType::Dirt), 2);
+        history.record_harvest(change(1, TileType::Crop, TileType::Chinampa), 1);
+        history.finish_stroke();
+
+        assert_eq!(history.undo().map(|command| command.harvested), Some(3));
+        assert_eq!(history.redo().map(|command| command.harvested), Some(3));
+    }
+
+    #[test]
+    fn clear_forgets_everything() {
+        let mut history = PaintHistory::new(10);
+        history.record(change(0, TileType::Grass, TileType::Dirt));
+        history.finish_stroke();
+        history.record(change(1, TileType::Grass, TileType::Dirt));
+        history.clear();
+        history.finish_stroke();
+        assert!(history.undo().is_none());
+        assert!(history.redo().is_none());
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::{fmt, fs, path::Path};
+
+use image::RgbaImage;
+
+use crate::TileType;
+use crate::grid::MAX_GRID_SIZE;
+use crate::history::TileState;
+use crate::map::{MAP_FORMAT_VERSION, MapFile, MapTile};
+
+/// Which colour stands for which tile type in an imported image.
+///
+/// ```json
+/// { "#33cc33": "Grass", "#7f4019": "Dirt", "#0000ff": "Water" }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Palette {
+    entries: Vec<([u8; 3], TileType)>,
+}
+
+impl Default for Palette {
+    /// The tile colours, so images from `export-png` import unchanged.
+    fn default() -> Self {
+        Palette {
+            entries: TileType::ALL
+                .into_iter()
+                .map(|tile_type| {
+                    let [r, g, b, _] = tile_type.color().as_rgba_u8();
+                    ([r, g, b], tile_type)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Palette {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let colors: HashMap<String, TileType> =
+            serde_json::from_str(&contents).map_err(|err| err.to_string())?;
+        if colors.is_empty() {
+            return Err("palette has no colours".to_string());
+        }
+        let mut entries = colors
+            .into_iter()
+            .map(|(hex, tile_type)| Ok((parse_hex(&hex)?, tile_type)))
+            .collect::<Result<Vec<_>, String>>()?;
+        // Keep snapping deterministic when two entries are equally close.
+        entries.sort_by_key(|(color, _)| *color);
+        Ok(Palette { entries })
+    }
+
+    /// Tile type for `color`, and whether the colour was an exact match
+    /// rather than the nearest entry.
+    fn lookup(&self, color: [u8; 3]) -> (TileType, bool) {
+        let distance = |entry: &[u8; 3]| -> u32 {
+            entry
+                .iter()
+                .zip(color)
+                .map(|(a, b)| (*a as i32 - b as i32).pow(2) as u32)
+                .sum()
+        };
+        let (entry, tile_type) = self
+            .entries
+            .iter()
+            .min_by_key(|(entry, _)| distance(entry))
+            .expect("palettes are never empty");
+        (*tile_type, *entry == color)
+    }
+}
+
+/// Parses `#rrggbb`.
+fn parse_hex(hex: &str) -> Result<[u8; 3], String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid colour `{hex}`, expected #rrggbb"));
+    }
+    let value = u32::from_str_radix(digits, 16).map_err(|err| err.to_string())?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok([r, g, b])
+}
+
+/// A colour missing from the palette, and the tile type its pixels were
+/// given instead.
+#[derive(Debug)]
+pub struct SnappedColor {
+    pub color: [u8; 3],
+    pub tile_type: TileType,
+    pub tiles: usize,
+}
+
+impl fmt::Display for SnappedColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b] = self.color;
+        write!(
+            f,
+            "colour #{r:02x}{g:02x}{b:02x} is not in the palette; {} tiles snapped to {:?}",
+            self.tiles, self.tile_type
+        )
+    }
+}
+
+pub struct ImportedImage {
+    pub map: MapFile,
+    pub snapped: Vec<SnappedColor>,
+}
+
+/// Builds a map from an image with `scale` pixels per tile, reading the
+/// bottom-right pixel of each tile so images exported with grid lines come
+/// back unchanged. The top row of the image is the north edge of the map.
+pub fn import_image(path: &Path, palette: &Palette, scale: u32) -> Result<ImportedImage, String> {
+    let image = image::open(path).map_err(|err| err.to_string())?.to_rgba8();
+    image_to_map(&image, palette, scale)
+}
+
+fn image_to_map(image: &RgbaImage, palette: &Palette, scale: u32) -> Result<ImportedImage, String> {
+    let scale = scale.max(1);
+    let (width, height) = (image.width() / scale, image.height() / scale);
+    let valid_size = 1..=MAX_GRID_SIZE;
+    if !valid_size.contains(&width) || !valid_size.contains(&height) {
+        return Err(format!(
+            "a {}x{} image at {scale} pixels per tile makes a {width}x{height} map, \
+             but maps must be between 1x1 and {MAX_GRID_SIZE}x{MAX_GRID_SIZE}",
+            image.width(),
+            image.height()
+        ));
+    }
+
+    let mut snapped: HashMap<[u8; 3], SnappedColor> = HashMap::new();
+    let mut tiles = Vec::with_capacity((width * height) as usize);
+    for row in 0..height {
+        for x in 0..width {
+            let [r, g, b, _] = image
+                .get_pixel((x + 1) * scale - 1, (row + 1) * scale - 1)
+                .0;
+            let (tile_type, exact) = palette.lookup([r, g, b]);
+            if !exact {
+                snapped
+                    .entry([r, g, b])
+                    .or_insert(SnappedColor {
+                        color: [r, g, b],
+                        tile_type,
+                        tiles: 0,
+                    })
+                    .tiles += 1;
+            }
+            let state = TileState {
+                tile_type,
+                growth: None,
+            };
+            tiles.push(MapTile::new(x, height - 1 - row, state));
+        }
+    }
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+
+    let mut snapped: Vec<SnappedColor> = snapped.into_values().collect();
+    snapped.sort_by_key(|snapped| std::cmp::Reverse(snapped.tiles));
+    Ok(ImportedImage {
+        map: MapFile {
+            version: MAP_FORMAT_VERSION,
+            width,
+            height,
+            seed: None,
+            terrain: None,
+            tiles,
+        },
+        snapped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+    use crate::export::{PngOptions, render_png};
+    use crate::grid::TypeMap;
+
+    fn rgb(tile_type: TileType) -> [u8; 3] {
+        let [r, g, b, _] = tile_type.color().as_rgba_u8();
+        [r, g, b]
+    }
+
+    fn pixel([r, g, b]: [u8; 3]) -> Rgba<u8> {
+        Rgba([r, g, b, 255])
+    }
+
+    #[test]
+    fn looks_up_exact_and_nearest_colours() {
+        let palette = Palette {
+            entries: vec![
+                ([0, 0, 255], TileType::Water),
+                ([0, 200, 0], TileType::Grass),
+            ],
+        };
+        assert_eq!(palette.lookup([0, 0, 255]), (TileType::Water, true));
+        assert_eq!(palette.lookup([10, 20, 230]), (TileType::Water, false));
+        assert_eq!(palette.lookup([30, 180, 10]), (TileType::Grass, false));
+    }
+
+    #[test]
+    fn parses_hex_colours() {
+        assert_eq!(parse_hex("#7f4019"), Ok([0x7f, 0x40, 0x19]));
+        assert_eq!(parse_hex("33CC33"), Ok([0x33, 0xcc, 0x33]));
+        assert!(parse_hex("#fff").is_err());
+        assert!(parse_hex("#gg0000").is_err());
+    }
+
+    #[test]
+    fn top_row_is_the_north_edge() {
+        let mut image = RgbaImage::from_pixel(2, 2, pixel(rgb(TileType::Grass)));
+        image.put_pixel(0, 0, pixel(rgb(TileType::Water)));
+        image.put_pixel(1, 1, pixel(rgb(TileType::Dirt)));
+
+        let imported = image_to_map(&image, &Palette::default(), 1).unwrap();
+        let map = imported.map;
+        assert_eq!(map.type_at(0, 1), TileType::Water);
+        assert_eq!(map.type_at(1, 1), TileType::Grass);
+        assert_eq!(map.type_at(0, 0), TileType::Grass);
+        assert_eq!(map.type_at(1, 0), TileType::Dirt);
+        assert!(imported.snapped.is_empty());
+    }
+
+    #[test]
+    fn reads_the_bottom_right_pixel_of_each_tile() {
+        // Every pixel but the bottom-right one of the 3x3 tile is water.
+        let mut image = RgbaImage::from_pixel(3, 3, pixel(rgb(TileType::Water)));
+        image.put_pixel(2, 2, pixel(rgb(TileType::Chinampa)));
+        let map = image_to_map(&image, &Palette::default(), 3).unwrap().map;
+        assert_eq!((map.width, map.height), (1, 1));
+        assert_eq!(map.type_at(0, 0), TileType::Chinampa);
+    }
+
+    #[test]
+    fn counts_the_tiles_of_each_snapped_colour() {
+        let off_grass = [rgb(TileType::Grass)[0] ^ 1, rgb(TileType::Grass)[1], 0];
+        let off_water = [1, 1, 250];
+        let mut image = RgbaImage::from_pixel(4, 1, pixel(off_grass));
+        image.put_pixel(0, 0, pixel(off_water));
+        image.put_pixel(1, 0, pixel(rgb(TileType::Dirt)));
+
+        let imported = image_to_map(&image, &Palette::default(), 1).unwrap();
+        let snapped: Vec<_> = imported
+            .snapped
+            .iter()
+            .map(|snapped| (snapped.color, snapped.tile_type, snapped.tiles))
+            .collect();
+        // Most frequent first.
+        assert_eq!(
+            snapped,
+            vec![
+                (off_grass, TileType::Grass, 2),
+                (off_water, TileType::Water, 1),
+            ]
+        );
+        assert_eq!(imported.map.type_at(1, 0), TileType::Dirt);
+    }
+
+    #[test]
+    fn exported_images_import_unchanged() {
+        let (width, height) = (5, 4);
+        let types: TypeMap = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| ((x, y), TileType::ALL[((x + 2 * y) % 5) as usize]))
+            .collect();
+        let options = PngOptions {
+            scale: 6,
+            grid_lines: true,
+        };
+        let image = render_png(width, height, &types, options).unwrap();
+
+        let imported = image_to_map(&image, &Palette::default(), options.scale).unwrap();
+        assert!(imported.snapped.is_empty());
+        assert_eq!((imported.map.width, imported.map.height), (width, height));
+        for ((x, y), tile_type) in types {
+            assert_eq!(imported.map.type_at(x, y), tile_type, "tile ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn rejects_images_smaller_than_a_tile() {
+        let image = RgbaImage::new(3, 8);
+        assert!(image_to_map(&image, &Palette::default(), 4).is_err());
+    }
+}
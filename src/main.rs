@@ -1,9 +1,81 @@
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+mod actions;
+mod ascii;
+mod autotile;
+mod camera;
+mod chinampa;
+mod chunks;
+mod cli;
+mod crops;
+mod export;
+mod generation;
+mod grid;
+mod headless;
+mod history;
+mod import;
+mod map;
+mod moisture;
+mod new_map;
+mod settings;
+mod tiled;
+mod tileset;
+mod tools;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
 use bevy::prelude::*;
-use rand::random;
+use serde::{Deserialize, Serialize};
 
-const TILE_SIZE: f32 = 32.0;
-const GRID_WIDTH: u32 = 10;
-const GRID_HEIGHT: u32 = 10;
+use actions::{
+    Action, ActionEvent, ActionState, ActionsSuspended, BindingsPath, action_input_system,
+    palette_action_system,
+};
+use autotile::{AutotileMask, autotile_system};
+use camera::{camera_fit_system, camera_pan_system, camera_zoom_system};
+use chinampa::{Fertility, chinampa_fertility_system};
+use chunks::{ChunkStore, LoadedChunks, chunk_modified_system, chunk_streaming_system};
+use cli::{CliArgs, Command};
+use crops::{
+    CropGrowth, GrowthStage, HarvestYield, YieldText, crop_growth_system, tile_color,
+    yield_text_system,
+};
+use export::{ExportPngEvent, export_png_system};
+use generation::{GardenRng, MapSeed, SeedText, seed_text_system};
+use import::{Palette, import_image};
+use grid::{GridConfig, TileGrid, TypeMap, tile_grid_system, tile_types};
+use history::{
+    PaintHistory, TileChange, TileState, finish_stroke_system, undo_redo_system,
+};
+use map::{
+    DEFAULT_MAP_PATH, LoadMapEvent, MapAction, MapFile, MapPath, SaveMapEvent, load_map_system,
+    map_action_button_system, save_map_system,
+};
+use moisture::{
+    Moisture, RainEvent, RainTimer, moisture_decay_system, rain_system, rain_timer_system,
+    recompute_moisture_system,
+};
+use new_map::{
+    NewMapDialog, NewMapEvent, new_map_control_system, new_map_dialog_system,
+    new_map_system, new_map_text_system, setup_new_map_dialog,
+};
+use settings::{
+    SettingsButton, SettingsScreen, binding_capture_system, settings_button_system,
+    settings_suspend_system, settings_text_system, settings_toggle_system, setup_bindings,
+    setup_settings_screen,
+};
+use tiled::TiledTable;
+use tileset::{
+    DEFAULT_TILESET_PATH, Tileset, TilesetPath, setup_tileset, tile_appearance_system,
+    tileset_load_system,
+};
+use tools::{
+    BrushControl, BrushSettings, BrushText, CursorTile, PaintTool, SelectedTool, ToolDrag,
+    ToolPreview, brush_control_system, brush_footprint, brush_text_system,
+    hover_outline_system, preview_marker_system, setup_hover_outline, tool_button_system,
+    tool_footprint,
+};
 
 #[derive(Component)]
 struct Tile;
@@ -14,131 +86,607 @@ struct TilePosition {
     y: u32,
 }
 
-#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 enum TileType {
     Grass,
     Dirt,
     Water,
     Crop,
+    /// Raised bed built in shallow water, the traditional Aztec field.
+    Chinampa,
 }
 
 impl TileType {
+    /// Every tile type, in the order the palette shows them.
+    const ALL: [TileType; 5] = [
+        TileType::Grass,
+        TileType::Dirt,
+        TileType::Water,
+        TileType::Crop,
+        TileType::Chinampa,
+    ];
+
     fn color(&self) -> Color {
         match self {
             TileType::Grass => Color::GREEN,
             TileType::Dirt => Color::rgb(0.5, 0.25, 0.1),
             TileType::Water => Color::BLUE,
             TileType::Crop => Color::rgb(0.1, 0.5, 0.1),
+            TileType::Chinampa => Color::rgb(0.35, 0.4, 0.2),
+        }
+    }
+
+    fn is_land(&self) -> bool {
+        *self != TileType::Water
+    }
+
+    /// Whether a tile of this type may be painted over `current`, given the
+    /// types of the tiles orthogonally adjacent to it.
+    fn can_be_placed_on(&self, current: TileType, neighbors: &[TileType]) -> bool {
+        match self {
+            TileType::Crop => matches!(current, TileType::Dirt | TileType::Chinampa),
+            TileType::Chinampa => {
+                current == TileType::Water && neighbors.iter().any(TileType::is_land)
+            }
+            _ => true,
         }
     }
+
+    /// [`TileType::can_be_placed_on`] for the tile at `(x, y)`, looking the
+    /// tile and its neighbours up with `type_at`.
+    fn can_be_placed_at(
+        &self,
+        grid: &GridConfig,
+        (x, y): (u32, u32),
+        type_at: impl Fn(u32, u32) -> Option<TileType>,
+    ) -> bool {
+        let Some(current) = type_at(x, y) else {
+            return false;
+        };
+        let adjacent: Vec<TileType> = grid
+            .neighbors(x, y)
+            .filter_map(|(x, y)| type_at(x, y))
+            .collect();
+        self.can_be_placed_on(current, &adjacent)
+    }
+}
+
+impl std::str::FromStr for TileType {
+    type Err = String;
+
+    /// Parses a tile type by name, ignoring case.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        TileType::ALL
+            .into_iter()
+            .find(|tile_type| format!("{tile_type:?}").eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown tile type `{name}`"))
+    }
 }
 
 #[derive(Resource, PartialEq, Eq, Clone, Copy)]
 struct SelectedTileType(TileType);
 
+/// Tile type [`Action::Erase`] resets tiles to, from `--erase-type`.
+#[derive(Resource, Clone, Copy)]
+struct EraseTileType(TileType);
+
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let args = match CliArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(2);
+        }
+    };
+    let mut grid = match args.grid_config() {
+        Ok(grid) => grid,
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(2);
+        }
+    };
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+
+    let tiled_table = load_tiled_table(&args);
+    // Tiles come from the `--map` file or `--image` where they list them and
+    // are generated from the seed everywhere else.
+    let loaded = match (&args.map, &args.image) {
+        (Some(path), _) => match map::load_map(path, &tiled_table) {
+            Ok(map) => Some((path.clone(), map)),
+            Err(err) => {
+                eprintln!("error: could not load {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        (None, Some(path)) => Some((path.with_extension("json"), import_map_image(&args, path))),
+        (None, None) => None,
+    };
+    let mut store = ChunkStore::default();
+    let mut terrain = args.terrain;
+    let (map_path, map_seed) = match loaded {
+        Some((path, map)) => {
+            grid.width = map.width;
+            grid.height = map.height;
+            terrain = map.terrain.unwrap_or(terrain);
+            store.insert_map(&map);
+            (path, map.seed)
+        }
+        None => (PathBuf::from(DEFAULT_MAP_PATH), Some(seed)),
+    };
+
+    if args.command != Command::Edit {
+        if let Err(err) = headless::run(&args, grid, terrain, store, map_seed, tiled_table) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
         .insert_resource(SelectedTileType(TileType::Grass))
-        .add_systems(Startup, (setup_camera, spawn_tiles, setup_ui))
-        .add_systems(Update, (mouse_click_system, tile_hover_system, tile_type_button_system))
+        .init_resource::<HarvestYield>()
+        .init_resource::<RainTimer>()
+        .insert_resource(PaintHistory::new(args.history_depth))
+        .insert_resource(SelectedTool(PaintTool::Brush))
+        .init_resource::<BrushSettings>()
+        .insert_resource(EraseTileType(args.erase_type))
+        .init_resource::<CursorTile>()
+        .init_resource::<TileGrid>()
+        .init_resource::<LoadedChunks>()
+        .init_resource::<ToolDrag>()
+        .init_resource::<ToolPreview>()
+        .init_resource::<NewMapDialog>()
+        .init_resource::<Tileset>()
+        .init_resource::<ActionState>()
+        .init_resource::<ActionsSuspended>()
+        .init_resource::<SettingsScreen>()
+        .insert_resource(
+            args.bindings
+                .clone()
+                .map_or_else(BindingsPath::user_default, BindingsPath),
+        )
+        .insert_resource(TilesetPath {
+            explicit: args.tileset.is_some(),
+            path: args
+                .tileset
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_TILESET_PATH)),
+        })
+        .insert_resource(GardenRng::new(seed))
+        .add_event::<ActionEvent>()
+        .insert_resource(args.png_options())
+        .insert_resource(tiled_table)
+        .add_event::<ExportPngEvent>()
+        .add_event::<SaveMapEvent>()
+        .add_event::<LoadMapEvent>()
+        .add_event::<NewMapEvent>()
+        .add_event::<RainEvent>();
+    add_editor_systems(&mut app);
+
+    app.insert_resource(MapPath(map_path))
+        .insert_resource(MapSeed(map_seed))
+        .insert_resource(grid)
+        .insert_resource(terrain)
+        .insert_resource(store)
         .run();
 }
 
+/// Every system of the editor, kept apart from `main` so tests can check
+/// the schedule without opening a window.
+fn add_editor_systems(app: &mut App) -> &mut App {
+    app.add_systems(
+        Startup,
+        (
+            setup_camera,
+            setup_hover_outline,
+            setup_tileset,
+            setup_bindings,
+            setup_ui,
+            setup_new_map_dialog,
+            setup_settings_screen,
+        ),
+    )
+    .add_systems(
+        Update,
+        (
+            tile_grid_system,
+            cursor_tile_system.after(tile_grid_system),
+            autotile_system.after(tile_grid_system),
+            mouse_click_system.after(cursor_tile_system),
+            tool_preview_system
+                .after(cursor_tile_system)
+                .after(mouse_click_system),
+            preview_marker_system.after(tool_preview_system),
+            hover_outline_system.after(tool_preview_system),
+            tile_type_button_system,
+            tool_button_system,
+            brush_control_system,
+            brush_text_system,
+            (
+                map_action_button_system,
+                save_map_system,
+                export_png_system,
+                load_map_system,
+                new_map_dialog_system,
+                new_map_control_system,
+                new_map_text_system,
+                new_map_system,
+            ),
+            yield_text_system,
+            seed_text_system,
+            finish_stroke_system.after(mouse_click_system),
+            pick_and_erase_system.after(cursor_tile_system),
+            selected_tile_button_system,
+            undo_redo_system
+                .after(tile_grid_system)
+                .after(action_input_system),
+        ),
+    )
+    .add_systems(
+        Update,
+        (
+            action_input_system,
+            palette_action_system,
+            camera_pan_system,
+            camera_zoom_system,
+            camera_fit_system,
+        )
+            .chain()
+            .before(cursor_tile_system),
+    )
+    .add_systems(
+        Update,
+        (
+            binding_capture_system,
+            settings_button_system,
+            settings_toggle_system,
+            settings_suspend_system,
+            settings_text_system,
+        )
+            .chain()
+            .after(action_input_system),
+    )
+    .add_systems(
+        Update,
+        (
+            recompute_moisture_system,
+            chinampa_fertility_system,
+            rain_timer_system,
+            rain_system,
+            moisture_decay_system,
+            crop_growth_system,
+        )
+            .chain()
+            .after(mouse_click_system)
+            .after(undo_redo_system)
+            .after(load_map_system)
+            .after(new_map_system),
+    )
+    .add_systems(
+        Update,
+        (
+            chunk_modified_system,
+            chunk_streaming_system,
+            tileset_load_system,
+            tile_appearance_system,
+        )
+            .chain()
+            .after(crop_growth_system)
+            .after(camera_fit_system),
+    )
+}
+
+/// Reads the `--tiled-table` mapping, or the default table.
+fn load_tiled_table(args: &CliArgs) -> TiledTable {
+    let Some(path) = &args.tiled_table else {
+        return TiledTable::default();
+    };
+    match TiledTable::load(path) {
+        Ok(table) => table,
+        Err(err) => {
+            eprintln!("error: could not load Tiled table {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Builds the map from `--image`, warning about colours that are not in
+/// the palette.
+fn import_map_image(args: &CliArgs, path: &Path) -> MapFile {
+    let palette = match &args.palette {
+        Some(palette_path) => match Palette::load(palette_path) {
+            Ok(palette) => palette,
+            Err(err) => {
+                eprintln!("error: could not load palette {}: {err}", palette_path.display());
+                std::process::exit(1);
+            }
+        },
+        None => Palette::default(),
+    };
+    match import_image(path, &palette, args.scale.unwrap_or(1)) {
+        Ok(imported) => {
+            for snapped in &imported.snapped {
+                eprintln!("warning: {}: {snapped}", path.display());
+            }
+            imported.map
+        }
+        Err(err) => {
+            eprintln!("error: could not import {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    }
+}
+
 fn setup_camera(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
 
-fn spawn_tiles(mut commands: Commands) {
-    for y in 0..GRID_HEIGHT {
-        for x in 0..GRID_WIDTH {
-            let pos_x = x as f32 * TILE_SIZE - (GRID_WIDTH as f32 * TILE_SIZE / 2.0);
-            let pos_y = y as f32 * TILE_SIZE - (GRID_HEIGHT as f32 * TILE_SIZE / 2.0);
+fn spawn_tile(
+    commands: &mut Commands,
+    grid: &GridConfig,
+    x: u32,
+    y: u32,
+    tile_type: TileType,
+    growth: Option<CropGrowth>,
+) -> Entity {
+    let growth = (tile_type == TileType::Crop)
+        .then(|| growth.unwrap_or(CropGrowth::new(GrowthStage::Seeded, TileType::Dirt)));
 
-            let tile_type = match random::<u8>() % 4 {
-                0 => TileType::Grass,
-                1 => TileType::Dirt,
-                2 => TileType::Water,
-                _ => TileType::Crop,
-            };
+    let mut tile = commands.spawn(SpriteBundle {
+        sprite: Sprite {
+            color: tile_color(&tile_type, growth.as_ref()),
+            custom_size: Some(Vec2::splat(grid.tile_size - 2.0)),
+            ..default()
+        },
+        transform: Transform::from_translation(grid.tile_translation(x, y)),
+        ..default()
+    });
+    tile.insert(Tile)
+        .insert(TilePosition { x, y })
+        .insert(tile_type)
+        .insert(AutotileMask::default())
+        .insert(Moisture::default())
+        .insert(Fertility::default());
+    if let Some(growth) = growth {
+        tile.insert(growth);
+    }
+    tile.id()
+}
 
-            commands
-                .spawn(SpriteBundle {
-                    sprite: Sprite {
-                        color: tile_type.color(),
-                        custom_size: Some(Vec2::splat(TILE_SIZE - 2.0)),
-                        ..default()
-                    },
-                    transform: Transform::from_xyz(pos_x, pos_y, 0.0),
-                    ..default()
-                })
-                .insert(Tile)
-                .insert(TilePosition { x, y })
-                .insert(tile_type);
+/// Writes `state` into a tile, keeping its crop component in sync. The
+/// sprite follows in `tile_appearance_system`.
+fn apply_tile_state(
+    commands: &mut Commands,
+    entity: Entity,
+    tile_type: &mut TileType,
+    state: TileState,
+) {
+    *tile_type = state.tile_type;
+    match state.growth {
+        Some(growth) => {
+            commands.entity(entity).insert(growth);
+        }
+        None => {
+            commands.entity(entity).remove::<CropGrowth>();
         }
     }
 }
 
-fn mouse_click_system(
+/// Finds the tile under the cursor so painting and previews share one
+/// hit test. The cursor counts as off the grid while it is over the UI.
+fn cursor_tile_system(
     windows: Query<&Window>,
-    buttons: Res<ButtonInput<MouseButton>>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
-    mut tiles: Query<(&mut Sprite, &Transform, &mut TileType)>,
-    selected: Res<SelectedTileType>,
+    ui: Query<&Interaction>,
+    grid: Res<GridConfig>,
+    tile_grid: Res<TileGrid>,
+    mut cursor: ResMut<CursorTile>,
 ) {
+    if ui.iter().any(|interaction| *interaction != Interaction::None) {
+        cursor.set_if_neq(CursorTile(None));
+        return;
+    }
+
     let window = windows.single();
-    if buttons.just_pressed(MouseButton::Left) {
-        if let Some(cursor_pos) = window.cursor_position() {
-            let (camera, camera_transform) = camera_q.single();
-            if let Some(world_pos) = camera
-                .viewport_to_world(camera_transform, cursor_pos)
-                .map(|r| r.origin.truncate())
-            {
-                for (mut sprite, transform, mut tile_type) in &mut tiles {
-                    let pos = transform.translation.truncate();
-                    let half_size = TILE_SIZE / 2.0;
-                    let in_x = (world_pos.x - pos.x).abs() < half_size;
-                    let in_y = (world_pos.y - pos.y).abs() < half_size;
-
-                    if in_x && in_y {
-                        *tile_type = selected.0;
-                        sprite.color = tile_type.color();
-                    }
-                }
-            }
+    let (camera, camera_transform) = camera_q.single();
+    let hovered = window
+        .cursor_position()
+        .and_then(|cursor_pos| camera.viewport_to_world(camera_transform, cursor_pos))
+        .map(|r| r.origin.truncate())
+        .and_then(|world_pos| grid.world_to_tile(world_pos))
+        .filter(|&(x, y)| tile_grid.get(x, y).is_some());
+    cursor.set_if_neq(CursorTile(hovered));
+}
+
+fn mouse_click_system(
+    mut commands: Commands,
+    state: Res<ActionState>,
+    cursor: Res<CursorTile>,
+    tool: Res<SelectedTool>,
+    brush: Res<BrushSettings>,
+    mut drag: ResMut<ToolDrag>,
+    grid: Res<GridConfig>,
+    tile_grid: Res<TileGrid>,
+    // One query for reading and writing tile types, as Bevy refuses a second
+    // one alongside a mutable borrow.
+    mut tiles: Query<(&TilePosition, &mut TileType, Option<&CropGrowth>, &Fertility), With<Tile>>,
+    selected: Res<SelectedTileType>,
+    mut harvest: ResMut<HarvestYield>,
+    mut history: ResMut<PaintHistory>,
+) {
+    let released_anchor = if state.just_released(Action::Paint) {
+        drag.last = None;
+        drag.stroking = false;
+        drag.anchor.take()
+    } else {
+        None
+    };
+    let Some(cursor) = cursor.0 else {
+        // Leaving the grid breaks the stroke rather than bridging the gap.
+        drag.last = None;
+        return;
+    };
+
+    let targets = if let Some(anchor) = released_anchor {
+        // Only the fill tool needs to see the whole grid.
+        let types = if tool.0 == PaintTool::Fill {
+            tile_types(tiles.iter().map(|(pos, tile_type, _, _)| (pos, tile_type)))
+        } else {
+            TypeMap::new()
+        };
+        tool_footprint(&grid, tool.0, *brush, &types, Some(anchor), cursor)
+    } else if state.just_pressed(Action::Paint) {
+        if tool.0 != PaintTool::Brush {
+            drag.anchor = Some(cursor);
+            return;
+        }
+
+        // Clicking a ripe crop with the brush harvests it instead of painting.
+        // The harvest is undone like a stroke, yield included.
+        if let Some(entity) = tile_grid.get(cursor.0, cursor.1)
+            && let Ok((_, mut tile_type, Some(growth), fertility)) = tiles.get_mut(entity)
+            && growth.stage == GrowthStage::Mature
+        {
+            let before = TileState {
+                tile_type: *tile_type,
+                growth: Some(*growth),
+            };
+            let after = TileState {
+                tile_type: growth.bed,
+                growth: None,
+            };
+            let tile_yield = fertility.harvest_yield();
+            apply_tile_state(&mut commands, entity, &mut tile_type, after);
+            let change = TileChange {
+                x: cursor.0,
+                y: cursor.1,
+                before,
+                after,
+            };
+            history.record_harvest(change, tile_yield);
+            harvest.0 += tile_yield;
+            return;
+        }
+        drag.last = Some(cursor);
+        drag.stroking = true;
+        brush_footprint(&grid, cursor, *brush)
+    } else if state.pressed(Action::Paint) && tool.0 == PaintTool::Brush {
+        drag.continue_stroke(&grid, cursor, *brush)
+    } else {
+        return;
+    };
+
+    // Decide every tile against the grid as it was before this stroke
+    // segment, so placement rules do not depend on the order tiles are
+    // painted in.
+    let type_at = |x: u32, y: u32| {
+        let entity = tile_grid.get(x, y)?;
+        tiles.get(entity).ok().map(|(_, tile_type, _, _)| *tile_type)
+    };
+    let targets: HashSet<(u32, u32)> = targets.into_iter().collect();
+    let mut changes = Vec::new();
+    for (x, y) in targets {
+        let (Some(entity), Some(current)) = (tile_grid.get(x, y), type_at(x, y)) else {
+            continue;
+        };
+        if current != selected.0 && selected.0.can_be_placed_at(&grid, (x, y), type_at) {
+            changes.push((entity, x, y));
         }
     }
+
+    for (entity, x, y) in changes {
+        let Ok((_, mut tile_type, growth, _)) = tiles.get_mut(entity) else {
+            continue;
+        };
+        let before = TileState {
+            tile_type: *tile_type,
+            growth: growth.copied(),
+        };
+        let after = TileState {
+            tile_type: selected.0,
+            growth: (selected.0 == TileType::Crop)
+                .then(|| CropGrowth::new(GrowthStage::Seeded, *tile_type)),
+        };
+        apply_tile_state(&mut commands, entity, &mut tile_type, after);
+        history.record(TileChange { x, y, before, after });
+    }
 }
 
-fn tile_hover_system(
-    windows: Query<&Window>,
-    camera_q: Query<(&Camera, &GlobalTransform)>,
-    mut tiles: Query<(&Transform, &mut Sprite, &TileType)>,
+/// Picks up the type of the tile under the cursor on [`Action::PickTileType`]
+/// and resets it to the erase type on [`Action::Erase`], as a single undo
+/// step.
+fn pick_and_erase_system(
+    mut commands: Commands,
+    mut events: EventReader<ActionEvent>,
+    cursor: Res<CursorTile>,
+    tile_grid: Res<TileGrid>,
+    erase_type: Res<EraseTileType>,
+    mut selected: ResMut<SelectedTileType>,
+    mut history: ResMut<PaintHistory>,
+    mut tiles: Query<(&mut TileType, Option<&CropGrowth>)>,
 ) {
-    let window = windows.single();
-    if let Some(cursor_pos) = window.cursor_position() {
-        let (camera, camera_transform) = camera_q.single();
-        if let Some(world_pos) = camera
-            .viewport_to_world(camera_transform, cursor_pos)
-            .map(|r| r.origin.truncate())
-        {
-            for (transform, mut sprite, tile_type) in &mut tiles {
-                let pos = transform.translation.truncate();
-                let half_size = TILE_SIZE / 2.0;
-                let in_x = (world_pos.x - pos.x).abs() < half_size;
-                let in_y = (world_pos.y - pos.y).abs() < half_size;
-
-                if in_x && in_y {
-                    sprite.color = Color::YELLOW;
-                } else {
-                    sprite.color = tile_type.color();
+    for ActionEvent(action) in events.read() {
+        let Some((x, y)) = cursor.0 else {
+            continue;
+        };
+        let Some(entity) = tile_grid.get(x, y) else {
+            continue;
+        };
+        let Ok((mut tile_type, growth)) = tiles.get_mut(entity) else {
+            continue;
+        };
+        match action {
+            Action::PickTileType => selected.0 = *tile_type,
+            Action::Erase => {
+                if *tile_type == erase_type.0 {
+                    continue;
                 }
+                let before = TileState {
+                    tile_type: *tile_type,
+                    growth: growth.copied(),
+                };
+                let after = TileState {
+                    tile_type: erase_type.0,
+                    growth: (erase_type.0 == TileType::Crop)
+                        .then(|| CropGrowth::new(GrowthStage::Seeded, *tile_type)),
+                };
+                apply_tile_state(&mut commands, entity, &mut tile_type, after);
+                history.record(TileChange { x, y, before, after });
+                history.finish_stroke();
             }
+            _ => {}
         }
     }
 }
 
+fn tool_preview_system(
+    cursor: Res<CursorTile>,
+    tool: Res<SelectedTool>,
+    brush: Res<BrushSettings>,
+    drag: Res<ToolDrag>,
+    grid: Res<GridConfig>,
+    tiles: Query<(&TilePosition, &TileType), With<Tile>>,
+    mut preview: ResMut<ToolPreview>,
+) {
+    let tiles = match cursor.0 {
+        Some(cursor) => {
+            let types = if tool.0 == PaintTool::Fill {
+                tile_types(tiles.iter())
+            } else {
+                TypeMap::new()
+            };
+            tool_footprint(&grid, tool.0, *brush, &types, drag.anchor, cursor)
+        }
+        None => Vec::new(),
+    };
+    preview.set_if_neq(ToolPreview(tiles));
+}
+
 fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/Fira_Sans/FiraSans-Bold.ttf");
+
     commands.spawn(NodeBundle {
         style: Style {
             width: Val::Percent(100.0),
@@ -154,36 +702,153 @@ fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
         ..Default::default()
     })
     .with_children(|parent| {
-        for tile_type in [TileType::Grass, TileType::Dirt, TileType::Water, TileType::Crop] {
-            parent.spawn((
-                ButtonBundle {
-                    style: Style {
-                        width: Val::Px(80.0),
-                        height: Val::Px(40.0),
-                        margin: UiRect::all(Val::Px(5.0)),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    background_color: BackgroundColor(tile_type.color()),
-                    ..Default::default()
+        for tile_type in TileType::ALL {
+            spawn_button(parent, &font, &format!("{:?}", tile_type), tile_type.color(), tile_type);
+        }
+
+        for (action, label) in [
+            (MapAction::New, "New"),
+            (MapAction::Save, "Save"),
+            (MapAction::Load, "Load"),
+            (MapAction::ExportPng, "PNG"),
+        ] {
+            spawn_button(parent, &font, label, Color::GRAY, action);
+        }
+        spawn_button(parent, &font, "Keys", Color::GRAY, SettingsButton);
+
+        parent.spawn((
+            TextBundle::from_section(
+                "Yield: 0",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 16.0,
+                    color: Color::WHITE,
                 },
-                tile_type,
-            ))
-            .with_children(|parent| {
-                parent.spawn(TextBundle::from_section(
-                    format!("{:?}", tile_type),
-                    TextStyle {
-                        font: asset_server.load("fonts/Fira_Sans/FiraSans-Bold.ttf"),
-                        font_size: 16.0,
-                        color: Color::BLACK,
-                    },
-                ));
-            });
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(5.0)),
+                ..Default::default()
+            }),
+            YieldText,
+        ));
+
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(5.0)),
+                ..Default::default()
+            }),
+            SeedText,
+        ));
+    });
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Px(50.0),
+            position_type: PositionType::Absolute,
+            top: Val::Px(50.0),
+            left: Val::Px(0.0),
+            flex_direction: FlexDirection::Row,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        for tool in PaintTool::ALL {
+            spawn_button(parent, &font, tool.label(), Color::SILVER, tool);
         }
+
+        for (control, label) in [
+            (BrushControl::ToggleShape, "Shape"),
+            (BrushControl::Shrink, "Size -"),
+            (BrushControl::Grow, "Size +"),
+        ] {
+            spawn_button(parent, &font, label, Color::GRAY, control);
+        }
+
+        parent.spawn((
+            TextBundle::from_section(
+                "Square r1",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                margin: UiRect::all(Val::Px(5.0)),
+                ..Default::default()
+            }),
+            BrushText,
+        ));
     });
 }
 
+/// Spawns a palette-style button carrying `marker`, which the matching
+/// interaction system reacts to.
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    label: &str,
+    color: Color,
+    marker: impl Component,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(80.0),
+                    height: Val::Px(40.0),
+                    margin: UiRect::all(Val::Px(5.0)),
+                    border: UiRect::all(Val::Px(3.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor(color),
+                ..Default::default()
+            },
+            marker,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 16.0,
+                    color: Color::BLACK,
+                },
+            ));
+        });
+}
+
+/// Outlines the button of the selected tile type, however it was chosen.
+fn selected_tile_button_system(
+    selected: Res<SelectedTileType>,
+    mut buttons: Query<(&TileType, &mut BorderColor), With<Button>>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+    for (tile_type, mut border) in &mut buttons {
+        border.0 = if *tile_type == selected.0 {
+            Color::WHITE
+        } else {
+            Color::NONE
+        };
+    }
+}
+
 fn tile_type_button_system(
     interaction_query: Query<(&Interaction, &TileType, &BackgroundColor), (Changed<Interaction>, With<Button>)>,
     mut selected: ResMut<SelectedTileType>,
@@ -198,3 +863,116 @@ fn tile_type_button_system(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::ActionMap;
+
+    #[test]
+    fn editor_systems_can_be_scheduled_together() {
+        // Initialising a schedule is where Bevy rejects systems whose
+        // queries conflict, which the headless commands never reach.
+        let mut app = App::new();
+        add_editor_systems(&mut app);
+        let initialize = |world: &mut World, schedule: &mut Schedule| {
+            schedule.initialize(world).unwrap();
+        };
+        app.world.schedule_scope(Startup, initialize);
+        app.world.schedule_scope(Update, initialize);
+    }
+
+    /// The painting systems over a 3x3 grid with a ripe crop in the middle,
+    /// under the cursor.
+    fn harvest_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.insert_resource(SelectedTileType(TileType::Water))
+            .insert_resource(SelectedTool(PaintTool::Brush))
+            .insert_resource(CursorTile(Some((1, 1))))
+            .insert_resource(GridConfig {
+                width: 3,
+                height: 3,
+                ..Default::default()
+            })
+            .insert_resource(PaintHistory::new(10))
+            .init_resource::<BrushSettings>()
+            .init_resource::<ToolDrag>()
+            .init_resource::<TileGrid>()
+            .init_resource::<HarvestYield>()
+            .init_resource::<ChunkStore>()
+            .init_resource::<ActionMap>()
+            .init_resource::<ActionState>()
+            .init_resource::<ActionsSuspended>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .init_resource::<ButtonInput<GamepadButton>>()
+            .add_event::<ActionEvent>()
+            .add_systems(
+                Update,
+                (
+                    action_input_system,
+                    tile_grid_system,
+                    mouse_click_system,
+                    finish_stroke_system,
+                    undo_redo_system,
+                )
+                    .chain(),
+            );
+        let crop = app
+            .world
+            .spawn((
+                Tile,
+                TilePosition { x: 1, y: 1 },
+                TileType::Crop,
+                CropGrowth::new(GrowthStage::Mature, TileType::Dirt),
+                Fertility::default(),
+            ))
+            .id();
+        // Lets the tile grid pick up the tile before the click.
+        app.update();
+        (app, crop)
+    }
+
+    fn click(app: &mut App) {
+        let mut mouse = app.world.resource_mut::<ButtonInput<MouseButton>>();
+        mouse.press(MouseButton::Left);
+        app.update();
+        app.world
+            .resource_mut::<ButtonInput<MouseButton>>()
+            .clear();
+    }
+
+    #[test]
+    fn holding_the_button_after_a_harvest_paints_nothing() {
+        let (mut app, crop) = harvest_app();
+
+        click(&mut app);
+        assert_eq!(*app.world.get::<TileType>(crop).unwrap(), TileType::Dirt);
+        assert_eq!(app.world.resource::<HarvestYield>().0, 1);
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(*app.world.get::<TileType>(crop).unwrap(), TileType::Dirt);
+    }
+
+    #[test]
+    fn undoing_a_harvest_takes_back_its_yield() {
+        let (mut app, crop) = harvest_app();
+        click(&mut app);
+        let mut mouse = app.world.resource_mut::<ButtonInput<MouseButton>>();
+        mouse.release(MouseButton::Left);
+        app.update();
+
+        app.world.send_event(ActionEvent(Action::Undo));
+        app.update();
+        assert_eq!(*app.world.get::<TileType>(crop).unwrap(), TileType::Crop);
+        assert_eq!(app.world.resource::<HarvestYield>().0, 0);
+
+        // Redoing harvests it again, once.
+        app.world.send_event(ActionEvent(Action::Redo));
+        app.update();
+        assert_eq!(*app.world.get::<TileType>(crop).unwrap(), TileType::Dirt);
+        assert_eq!(app.world.resource::<HarvestYield>().0, 1);
+    }
+}
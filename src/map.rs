@@ -0,0 +1,527 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ascii::{AsciiError, is_ascii_path, parse_ascii, to_ascii};
+use crate::chunks::{ChunkStore, LoadedChunks, chunk_of};
+use crate::crops::{CropGrowth, GrowthStage};
+use crate::export::ExportPngEvent;
+use crate::generation::{MapSeed, TerrainParams};
+use crate::grid::{GridConfig, MAX_GRID_SIZE};
+use crate::history::{PaintHistory, TileState};
+use crate::moisture::Moisture;
+use crate::new_map::NewMapDialog;
+use crate::tiled::{TiledTable, is_tiled_path, parse_tiled, to_tiled};
+use crate::{Tile, TilePosition, TileType};
+
+/// Version written into every saved map. Bump it whenever the layout of
+/// [`MapFile`] changes in a way older readers cannot handle.
+///
+/// Version 2 added the optional crop growth stage, version 3 the bed a crop
+/// was planted on and version 4 seeded maps that only list edited chunks;
+/// older files still load.
+pub const MAP_FORMAT_VERSION: u32 = 4;
+
+/// Where maps are saved when no `--map` path was given.
+pub const DEFAULT_MAP_PATH: &str = "garden.json";
+
+/// On-disk representation of the garden grid.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MapFile {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Seed the rest of the map is generated from. When present, `tiles`
+    /// only lists the chunks that were edited; otherwise it covers the map.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terrain: Option<TerrainParams>,
+    pub tiles: Vec<MapTile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct MapTile {
+    pub x: u32,
+    pub y: u32,
+    pub tile_type: TileType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<GrowthStage>,
+    /// Tile under a crop, written only when it is not plain `Dirt`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bed: Option<TileType>,
+    /// Soil moisture when the map was saved. Tiles without one start at the
+    /// level their distance to water gives them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moisture: Option<f32>,
+}
+
+impl MapTile {
+    pub fn new(x: u32, y: u32, state: TileState) -> Self {
+        MapTile {
+            x,
+            y,
+            tile_type: state.tile_type,
+            stage: state.growth.map(|growth| growth.stage),
+            bed: state
+                .growth
+                .map(|growth| growth.bed)
+                .filter(|bed| *bed != TileType::Dirt),
+            moisture: None,
+        }
+    }
+
+    pub fn with_moisture(mut self, moisture: Option<f32>) -> Self {
+        self.moisture = moisture;
+        self
+    }
+
+    pub fn state(&self) -> TileState {
+        TileState {
+            tile_type: self.tile_type,
+            growth: self
+                .stage
+                .map(|stage| CropGrowth::new(stage, self.bed.unwrap_or(TileType::Dirt))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Ascii(AsciiError),
+    Tiled(String),
+    UnsupportedVersion(u32),
+    InvalidSize { width: u32, height: u32 },
+    OutOfBounds { x: u32, y: u32 },
+    DuplicateTile { x: u32, y: u32 },
+    MissingTiles(usize),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(err) => write!(f, "i/o error: {err}"),
+            MapError::Parse(err) => write!(f, "malformed map file: {err}"),
+            MapError::Ascii(err) => write!(f, "malformed text map: {err}"),
+            MapError::Tiled(err) => write!(f, "unsupported Tiled map: {err}"),
+            MapError::UnsupportedVersion(version) => write!(
+                f,
+                "map format version {version} is newer than supported version {MAP_FORMAT_VERSION}"
+            ),
+            MapError::InvalidSize { width, height } => write!(
+                f,
+                "map is {width}x{height} but must be between 1x1 and {MAX_GRID_SIZE}x{MAX_GRID_SIZE}"
+            ),
+            MapError::OutOfBounds { x, y } => write!(f, "tile ({x}, {y}) lies outside the map"),
+            MapError::DuplicateTile { x, y } => write!(f, "tile ({x}, {y}) is listed twice"),
+            MapError::MissingTiles(count) => write!(f, "{count} tiles are missing from the map"),
+        }
+    }
+}
+
+impl Error for MapError {}
+
+impl From<io::Error> for MapError {
+    fn from(err: io::Error) -> Self {
+        MapError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for MapError {
+    fn from(err: serde_json::Error) -> Self {
+        MapError::Parse(err)
+    }
+}
+
+impl From<AsciiError> for MapError {
+    fn from(err: AsciiError) -> Self {
+        MapError::Ascii(err)
+    }
+}
+
+impl MapFile {
+    /// Checks that the map can be spawned: a known version, supported
+    /// dimensions and at most one entry per position, with every position
+    /// covered unless the map has a seed to generate the rest from.
+    pub fn validate(&self) -> Result<(), MapError> {
+        if self.version > MAP_FORMAT_VERSION {
+            return Err(MapError::UnsupportedVersion(self.version));
+        }
+        let valid_size = 1..=MAX_GRID_SIZE;
+        if !valid_size.contains(&self.width) || !valid_size.contains(&self.height) {
+            return Err(MapError::InvalidSize {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        let mut seen = HashSet::new();
+        for tile in &self.tiles {
+            if tile.x >= self.width || tile.y >= self.height {
+                return Err(MapError::OutOfBounds {
+                    x: tile.x,
+                    y: tile.y,
+                });
+            }
+            if !seen.insert((tile.x, tile.y)) {
+                return Err(MapError::DuplicateTile {
+                    x: tile.x,
+                    y: tile.y,
+                });
+            }
+        }
+
+        match (self.width * self.height) as usize - seen.len() {
+            0 => Ok(()),
+            _ if self.seed.is_some() => Ok(()),
+            missing => Err(MapError::MissingTiles(missing)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl MapFile {
+    /// Type of the listed tile at `(x, y)`, for checking parsed maps.
+    pub fn type_at(&self, x: u32, y: u32) -> TileType {
+        self.tiles
+            .iter()
+            .find(|tile| (tile.x, tile.y) == (x, y))
+            .map(|tile| tile.tile_type)
+            .unwrap_or_else(|| panic!("tile ({x}, {y}) is not listed"))
+    }
+}
+
+/// Loads a JSON map, a text map when the path ends in `.txt` or a Tiled
+/// map when it ends in `.tmj`, reading its tiles through `table`.
+pub fn load_map(path: &Path, table: &TiledTable) -> Result<MapFile, MapError> {
+    let contents = fs::read_to_string(path)?;
+    let map: MapFile = if is_ascii_path(path) {
+        parse_ascii(&contents)?
+    } else if is_tiled_path(path) {
+        parse_tiled(&contents, table).map_err(MapError::Tiled)?
+    } else {
+        serde_json::from_str(&contents)?
+    };
+    map.validate()?;
+    Ok(map)
+}
+
+/// Saves a map in the format [`load_map`] picks for `path`.
+pub fn save_map(path: &Path, map: &MapFile, table: &TiledTable) -> Result<(), MapError> {
+    let contents = if is_ascii_path(path) {
+        to_ascii(map)
+    } else if is_tiled_path(path) {
+        to_tiled(map, table).map_err(MapError::Tiled)?
+    } else {
+        serde_json::to_string_pretty(map)?
+    };
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// File used by the save and load actions.
+#[derive(Resource)]
+pub struct MapPath(pub PathBuf);
+
+#[derive(Event)]
+pub struct SaveMapEvent;
+
+#[derive(Event)]
+pub struct LoadMapEvent;
+
+/// Marks the new/save/load/export buttons in the UI bar.
+#[derive(Component, Clone, Copy, Debug)]
+pub enum MapAction {
+    New,
+    Save,
+    Load,
+    ExportPng,
+}
+
+pub fn map_action_button_system(
+    interaction_query: Query<(&Interaction, &MapAction), Changed<Interaction>>,
+    mut save_events: EventWriter<SaveMapEvent>,
+    mut load_events: EventWriter<LoadMapEvent>,
+    mut export_events: EventWriter<ExportPngEvent>,
+    mut new_map_dialog: ResMut<NewMapDialog>,
+    grid: Res<GridConfig>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            match action {
+                MapAction::New => new_map_dialog.open_for(&grid),
+                MapAction::Save => {
+                    save_events.send(SaveMapEvent);
+                }
+                MapAction::Load => {
+                    load_events.send(LoadMapEvent);
+                }
+                MapAction::ExportPng => {
+                    export_events.send(ExportPngEvent);
+                }
+            }
+        }
+    }
+}
+
+/// Saves the stored chunks together with the loaded ones that were modified.
+/// Untouched chunks of a seeded map are left for the seed to regenerate.
+pub fn save_map_system(
+    mut events: EventReader<SaveMapEvent>,
+    tiles: Query<(&TilePosition, &TileType, Option<&CropGrowth>, &Moisture), With<Tile>>,
+    loaded: Res<LoadedChunks>,
+    store: Res<ChunkStore>,
+    grid: Res<GridConfig>,
+    seed: Res<MapSeed>,
+    terrain: Res<TerrainParams>,
+    path: Res<MapPath>,
+    table: Res<TiledTable>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+
+    let mut tiles_by_pos: HashMap<(u32, u32), MapTile> = store
+        .tiles()
+        .map(|((x, y), state)| {
+            let tile = MapTile::new(x, y, state).with_moisture(store.moisture(x, y));
+            ((x, y), tile)
+        })
+        .collect();
+    for (pos, tile_type, growth, moisture) in &tiles {
+        if loaded.is_modified(chunk_of(pos.x, pos.y)) {
+            let state = TileState {
+                tile_type: *tile_type,
+                growth: growth.copied(),
+            };
+            let tile = MapTile::new(pos.x, pos.y, state).with_moisture(Some(moisture.level));
+            tiles_by_pos.insert((pos.x, pos.y), tile);
+        }
+    }
+
+    let mut map = MapFile {
+        version: MAP_FORMAT_VERSION,
+        width: grid.width,
+        height: grid.height,
+        seed: seed.0,
+        terrain: seed.0.map(|_| *terrain),
+        tiles: tiles_by_pos.into_values().collect(),
+    };
+    map.tiles.sort_by_key(|tile| (tile.y, tile.x));
+
+    match save_map(&path.0, &map, &table) {
+        Ok(()) => info!("saved map to {}", path.0.display()),
+        Err(err) => error!("failed to save map to {}: {err}", path.0.display()),
+    }
+}
+
+pub fn load_map_system(
+    mut commands: Commands,
+    mut events: EventReader<LoadMapEvent>,
+    tiles: Query<Entity, With<Tile>>,
+    mut loaded: ResMut<LoadedChunks>,
+    mut store: ResMut<ChunkStore>,
+    mut grid: ResMut<GridConfig>,
+    path: Res<MapPath>,
+    table: Res<TiledTable>,
+    mut seed: ResMut<MapSeed>,
+    mut terrain: ResMut<TerrainParams>,
+    mut history: ResMut<PaintHistory>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+
+    let map = match load_map(&path.0, &table) {
+        Ok(map) => map,
+        Err(err) => {
+            error!("failed to load map from {}: {err}", path.0.display());
+            return;
+        }
+    };
+
+    for entity in &tiles {
+        commands.entity(entity).despawn();
+    }
+    loaded.clear();
+    store.clear();
+    store.insert_map(&map);
+    grid.width = map.width;
+    grid.height = map.height;
+    seed.0 = map.seed;
+    if let Some(map_terrain) = map.terrain {
+        *terrain = map_terrain;
+    }
+    history.clear();
+    info!("loaded map from {}", path.0.display());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Loads `contents` through a file named `name`, so the extension picks
+    /// the format.
+    fn load_str(name: &str, contents: &str) -> Result<MapFile, MapError> {
+        // Tests run in parallel, so every call gets a file of its own.
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let unique = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path =
+            env::temp_dir().join(format!("aztlan-map-{}-{unique}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let map = load_map(&path, &TiledTable::default());
+        fs::remove_file(&path).unwrap();
+        map
+    }
+
+    fn load_json(contents: &str) -> Result<MapFile, MapError> {
+        load_str("map.json", contents)
+    }
+
+    fn tile(x: u32, y: u32) -> MapTile {
+        MapTile::new(
+            x,
+            y,
+            TileState {
+                tile_type: TileType::Grass,
+                growth: None,
+            },
+        )
+    }
+
+    fn map(width: u32, height: u32, tiles: Vec<MapTile>) -> MapFile {
+        MapFile {
+            version: MAP_FORMAT_VERSION,
+            width,
+            height,
+            seed: None,
+            terrain: None,
+            tiles,
+        }
+    }
+
+    #[test]
+    fn loads_version_1_files() {
+        let map = load_json(
+            r#"{
+                "version": 1, "width": 2, "height": 1,
+                "tiles": [
+                    { "x": 0, "y": 0, "tile_type": "Water" },
+                    { "x": 1, "y": 0, "tile_type": "Crop" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(map.type_at(0, 0), TileType::Water);
+        let crop = map.tiles[1];
+        assert_eq!(crop.tile_type, TileType::Crop);
+        assert!(crop.stage.is_none() && crop.bed.is_none() && crop.moisture.is_none());
+        assert!(crop.state().growth.is_none());
+    }
+
+    #[test]
+    fn loads_version_3_crop_beds() {
+        let map = load_json(
+            r#"{
+                "version": 3, "width": 1, "height": 1,
+                "tiles": [
+                    { "x": 0, "y": 0, "tile_type": "Crop", "stage": "Mature", "bed": "Chinampa" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let growth = map.tiles[0].state().growth.unwrap();
+        assert_eq!(growth.stage, GrowthStage::Mature);
+        assert_eq!(growth.bed, TileType::Chinampa);
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let mut newer = map(1, 1, vec![tile(0, 0)]);
+        newer.version = MAP_FORMAT_VERSION + 1;
+        let err = load_json(&serde_json::to_string(&newer).unwrap()).unwrap_err();
+        assert!(matches!(err, MapError::UnsupportedVersion(v) if v == MAP_FORMAT_VERSION + 1));
+    }
+
+    #[test]
+    fn rejects_unsupported_sizes() {
+        assert!(matches!(
+            map(0, 3, Vec::new()).validate(),
+            Err(MapError::InvalidSize {
+                width: 0,
+                height: 3
+            })
+        ));
+        let too_wide = MAX_GRID_SIZE + 1;
+        assert!(matches!(
+            map(too_wide, 1, Vec::new()).validate(),
+            Err(MapError::InvalidSize { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_tiles_outside_the_map() {
+        let err = map(2, 2, vec![tile(0, 0), tile(2, 1)]).validate();
+        assert!(matches!(err, Err(MapError::OutOfBounds { x: 2, y: 1 })));
+    }
+
+    #[test]
+    fn rejects_duplicate_tiles() {
+        let err = map(2, 1, vec![tile(0, 0), tile(1, 0), tile(0, 0)]).validate();
+        assert!(matches!(err, Err(MapError::DuplicateTile { x: 0, y: 0 })));
+    }
+
+    #[test]
+    fn missing_tiles_need_a_seed() {
+        let mut sparse = map(2, 2, vec![tile(1, 1)]);
+        assert!(matches!(sparse.validate(), Err(MapError::MissingTiles(3))));
+        sparse.seed = Some(1);
+        assert!(sparse.validate().is_ok());
+    }
+
+    #[test]
+    fn reports_unreadable_files() {
+        let missing = env::temp_dir().join("aztlan-map-does-not-exist.json");
+        let err = load_map(&missing, &TiledTable::default()).unwrap_err();
+        assert!(matches!(err, MapError::Io(_)));
+
+        assert!(matches!(
+            load_json("{ \"version\": 4, "),
+            Err(MapError::Parse(_))
+        ));
+        assert!(matches!(
+            load_str("map.txt", "..\n.x\n"),
+            Err(MapError::Ascii(_))
+        ));
+        assert!(matches!(
+            load_str(
+                "map.tmj",
+                r#"{ "width": 1, "height": 1, "infinite": true, "layers": [], "tilesets": [] }"#
+            ),
+            Err(MapError::Tiled(_))
+        ));
+    }
+
+    #[test]
+    fn saving_round_trips() {
+        let mut saved = map(2, 1, vec![tile(0, 0), tile(1, 0).with_moisture(Some(0.5))]);
+        saved.tiles[0].tile_type = TileType::Chinampa;
+        let json = serde_json::to_string_pretty(&saved).unwrap();
+        let loaded = load_json(&json).unwrap();
+        assert_eq!(loaded.type_at(0, 0), TileType::Chinampa);
+        assert_eq!(loaded.tiles[1].moisture, Some(0.5));
+        assert_eq!(serde_json::to_string_pretty(&loaded).unwrap(), json);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::chinampa::is_raised_bed;
+use crate::crops::CropGrowth;
+use crate::grid::{GridConfig, TypeMap, tile_types};
+use crate::{Tile, TilePosition, TileType};
+
+/// Tiles further than this (Manhattan distance) from water get no moisture
+/// from it.
+const WATER_REACH: u32 = 4;
+
+/// Moisture lost per second while the soil dries back towards its baseline.
+const DECAY_PER_SECOND: f32 = 0.02;
+
+/// Seconds between rain showers.
+const RAIN_INTERVAL: f32 = 60.0;
+
+/// Below this level crops stop growing and start to suffer from drought.
+pub const DRY_THRESHOLD: f32 = 0.2;
+
+/// Soil moisture of a tile, both in `0.0..=1.0`.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Moisture {
+    /// Current moisture, raised by rain and decaying over time.
+    pub level: f32,
+    /// Moisture the soil settles at, derived from the distance to water.
+    pub baseline: f32,
+}
+
+impl Moisture {
+    /// Multiplier applied to crop growth on this tile. Zero when the soil is
+    /// too dry for crops to grow at all.
+    pub fn growth_rate(&self) -> f32 {
+        if self.level < DRY_THRESHOLD {
+            0.0
+        } else {
+            0.5 + self.level
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct RainEvent;
+
+#[derive(Resource)]
+pub struct RainTimer(pub Timer);
+
+impl Default for RainTimer {
+    fn default() -> Self {
+        RainTimer(Timer::from_seconds(RAIN_INTERVAL, TimerMode::Repeating))
+    }
+}
+
+/// Baseline moisture for a tile `distance` steps away from the nearest water.
+fn baseline_for_distance(distance: Option<u32>) -> f32 {
+    match distance {
+        Some(distance) if distance <= WATER_REACH => {
+            1.0 - distance as f32 / (WATER_REACH + 1) as f32
+        }
+        _ => 0.0,
+    }
+}
+
+/// Manhattan distance from spawned tiles to the nearest water tile, found
+/// with a multi-source breadth-first search. Tiles further than
+/// [`WATER_REACH`] from water are left out.
+fn water_distances(grid: &GridConfig, types: &TypeMap) -> HashMap<(u32, u32), u32> {
+    let mut distances = HashMap::new();
+    let mut queue = VecDeque::new();
+    for (&pos, tile_type) in types {
+        if *tile_type == TileType::Water {
+            distances.insert(pos, 0);
+            queue.push_back(pos);
+        }
+    }
+
+    while let Some((x, y)) = queue.pop_front() {
+        let distance = distances[&(x, y)];
+        if distance == WATER_REACH {
+            continue;
+        }
+        for neighbor in grid.neighbors(x, y) {
+            if types.contains_key(&neighbor) && !distances.contains_key(&neighbor) {
+                distances.insert(neighbor, distance + 1);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    distances
+}
+
+/// Recomputes baselines whenever a tile changes type, so moving water around
+/// immediately changes which soil stays wet. Chinampas are always saturated.
+pub fn recompute_moisture_system(
+    changed: Query<(), (With<Tile>, Changed<TileType>)>,
+    grid: Res<GridConfig>,
+    mut tiles: Query<(&TilePosition, &TileType, Option<&CropGrowth>, &mut Moisture), With<Tile>>,
+) {
+    if changed.is_empty() {
+        return;
+    }
+
+    let types = tile_types(tiles.iter().map(|(pos, tile_type, _, _)| (pos, tile_type)));
+    let distances = water_distances(&grid, &types);
+
+    for (pos, tile_type, growth, mut moisture) in &mut tiles {
+        let baseline = if is_raised_bed(tile_type, growth) {
+            1.0
+        } else {
+            baseline_for_distance(distances.get(&(pos.x, pos.y)).copied())
+        };
+        moisture.baseline = baseline;
+        moisture.level = moisture.level.max(baseline);
+    }
+}
+
+pub fn moisture_decay_system(time: Res<Time>, mut tiles: Query<&mut Moisture>) {
+    let decay = DECAY_PER_SECOND * time.delta_seconds();
+    for mut moisture in &mut tiles {
+        if moisture.level > moisture.baseline {
+            moisture.level = (moisture.level - decay).max(moisture.baseline);
+        }
+    }
+}
+
+pub fn rain_timer_system(
+    time: Res<Time>,
+    mut timer: ResMut<RainTimer>,
+    mut rain: EventWriter<RainEvent>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        rain.send(RainEvent);
+    }
+}
+
+pub fn rain_system(mut events: EventReader<RainEvent>, mut tiles: Query<&mut Moisture>) {
+    if events.read().count() == 0 {
+        return;
+    }
+    for mut moisture in &mut tiles {
+        moisture.level = 1.0;
+    }
+    info!("rain soaked the garden");
+}
@@ -0,0 +1,278 @@
+use bevy::prelude::*;
+
+use crate::chunks::{ChunkStore, LoadedChunks};
+use crate::generation::{GardenRng, MapSeed};
+use crate::grid::{DEFAULT_GRID_HEIGHT, DEFAULT_GRID_WIDTH, GridConfig, MAX_GRID_SIZE};
+use crate::history::PaintHistory;
+use crate::{Tile, spawn_button};
+
+/// State of the "new map" dialog while the user picks dimensions.
+#[derive(Resource)]
+pub struct NewMapDialog {
+    pub open: bool,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for NewMapDialog {
+    fn default() -> Self {
+        NewMapDialog {
+            open: false,
+            width: DEFAULT_GRID_WIDTH,
+            height: DEFAULT_GRID_HEIGHT,
+        }
+    }
+}
+
+impl NewMapDialog {
+    /// Opens the dialog with the current grid size filled in.
+    pub fn open_for(&mut self, grid: &GridConfig) {
+        self.open = true;
+        self.width = grid.width;
+        self.height = grid.height;
+    }
+
+    fn size_mut(&mut self, dimension: Dimension) -> &mut u32 {
+        match dimension {
+            Dimension::Width => &mut self.width,
+            Dimension::Height => &mut self.height,
+        }
+    }
+}
+
+/// Replaces the grid with a freshly generated one of the given size.
+#[derive(Event)]
+pub struct NewMapEvent {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Component)]
+pub struct NewMapPanel;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dimension {
+    Width,
+    Height,
+}
+
+/// Marks the buttons inside the dialog.
+#[derive(Component, Clone, Copy, Debug)]
+pub enum NewMapControl {
+    Shrink(Dimension),
+    Grow(Dimension),
+    Create,
+    Cancel,
+}
+
+#[derive(Component)]
+pub struct NewMapLabel(Dimension);
+
+pub fn setup_new_map_dialog(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/Fira_Sans/FiraSans-Bold.ttf");
+
+    // The full-window backdrop carries an `Interaction` so clicks on it do not
+    // reach the tiles underneath while the dialog is open.
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+                z_index: ZIndex::Global(10),
+                ..Default::default()
+            },
+            Interaction::default(),
+            NewMapPanel,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(Color::DARK_GRAY),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for dimension in [Dimension::Width, Dimension::Height] {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::Row,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                spawn_button(
+                                    parent,
+                                    &font,
+                                    "-",
+                                    Color::GRAY,
+                                    NewMapControl::Shrink(dimension),
+                                );
+                                parent.spawn((
+                                    TextBundle::from_section(
+                                        "",
+                                        TextStyle {
+                                            font: font.clone(),
+                                            font_size: 16.0,
+                                            color: Color::WHITE,
+                                        },
+                                    )
+                                    .with_style(Style {
+                                        width: Val::Px(120.0),
+                                        margin: UiRect::all(Val::Px(5.0)),
+                                        ..Default::default()
+                                    }),
+                                    NewMapLabel(dimension),
+                                ));
+                                spawn_button(
+                                    parent,
+                                    &font,
+                                    "+",
+                                    Color::GRAY,
+                                    NewMapControl::Grow(dimension),
+                                );
+                            });
+                    }
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            spawn_button(
+                                parent,
+                                &font,
+                                "Create",
+                                Color::SILVER,
+                                NewMapControl::Create,
+                            );
+                            spawn_button(
+                                parent,
+                                &font,
+                                "Cancel",
+                                Color::SILVER,
+                                NewMapControl::Cancel,
+                            );
+                        });
+                });
+        });
+}
+
+pub fn new_map_dialog_system(
+    dialog: Res<NewMapDialog>,
+    mut panels: Query<&mut Style, With<NewMapPanel>>,
+) {
+    if !dialog.is_changed() {
+        return;
+    }
+    for mut style in &mut panels {
+        style.display = if dialog.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+}
+
+/// Adjusts the requested size in steps of one, or ten while Shift is held.
+pub fn new_map_control_system(
+    interaction_query: Query<(&Interaction, &NewMapControl), Changed<Interaction>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut dialog: ResMut<NewMapDialog>,
+    mut events: EventWriter<NewMapEvent>,
+) {
+    let step = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        10
+    } else {
+        1
+    };
+
+    for (interaction, control) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *control {
+            NewMapControl::Shrink(dimension) => {
+                let size = dialog.size_mut(dimension);
+                *size = size.saturating_sub(step).max(1);
+            }
+            NewMapControl::Grow(dimension) => {
+                let size = dialog.size_mut(dimension);
+                *size = (*size + step).min(MAX_GRID_SIZE);
+            }
+            NewMapControl::Create => {
+                events.send(NewMapEvent {
+                    width: dialog.width,
+                    height: dialog.height,
+                });
+                dialog.open = false;
+            }
+            NewMapControl::Cancel => dialog.open = false,
+        }
+    }
+}
+
+pub fn new_map_text_system(
+    dialog: Res<NewMapDialog>,
+    mut labels: Query<(&mut Text, &NewMapLabel)>,
+) {
+    if !dialog.is_changed() {
+        return;
+    }
+    for (mut text, label) in &mut labels {
+        text.sections[0].value = match label.0 {
+            Dimension::Width => format!("Width: {}", dialog.width),
+            Dimension::Height => format!("Height: {}", dialog.height),
+        };
+    }
+}
+
+pub fn new_map_system(
+    mut commands: Commands,
+    mut events: EventReader<NewMapEvent>,
+    tiles: Query<Entity, With<Tile>>,
+    mut loaded: ResMut<LoadedChunks>,
+    mut store: ResMut<ChunkStore>,
+    mut grid: ResMut<GridConfig>,
+    mut rng: ResMut<GardenRng>,
+    mut seed: ResMut<MapSeed>,
+    mut history: ResMut<PaintHistory>,
+) {
+    let Some(event) = events.read().last() else {
+        return;
+    };
+
+    for entity in &tiles {
+        commands.entity(entity).despawn();
+    }
+    loaded.clear();
+    store.clear();
+    grid.width = event.width;
+    grid.height = event.height;
+    let new_seed = rng.next_seed();
+    seed.0 = Some(new_seed);
+    history.clear();
+    info!(
+        "created a new {}x{} map from seed {new_seed}",
+        grid.width, grid.height
+    );
+}
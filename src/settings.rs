@@ -0,0 +1,399 @@
+use bevy::prelude::*;
+
+use crate::actions::{
+    Action, ActionEvent, ActionMap, ActionsSuspended, Binding, BindingsPath, InputButton,
+    load_bindings,
+};
+use crate::spawn_button;
+
+/// Bindings shown per action on the settings screen.
+const SLOTS: usize = 3;
+
+const SLOT_COLOR: Color = Color::GRAY;
+const CONFLICT_COLOR: Color = Color::rgb(0.8, 0.3, 0.3);
+const CAPTURE_COLOR: Color = Color::rgb(0.9, 0.8, 0.3);
+
+/// State of the key bindings screen.
+#[derive(Resource, Default)]
+pub struct SettingsScreen {
+    pub open: bool,
+    /// Binding slot waiting for the next button press.
+    capturing: Option<(Action, usize)>,
+    /// Outcome of the last rebind, such as a conflict warning.
+    message: String,
+}
+
+#[derive(Component)]
+pub struct SettingsPanel;
+
+/// Opens the settings screen from the tool bar.
+#[derive(Component)]
+pub struct SettingsButton;
+
+/// One binding of one action; pressing it waits for a new button.
+#[derive(Component, Clone, Copy)]
+pub struct BindingSlot {
+    action: Action,
+    slot: usize,
+}
+
+#[derive(Component, Clone, Copy)]
+pub enum SettingsControl {
+    ResetDefaults,
+    Close,
+}
+
+#[derive(Component)]
+pub struct SettingsMessage;
+
+/// Loads the user's bindings before the first frame.
+pub fn setup_bindings(mut commands: Commands, path: Res<BindingsPath>) {
+    commands.insert_resource(load_bindings(&path.0));
+}
+
+pub fn setup_settings_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/Fira_Sans/FiraSans-Bold.ttf");
+    let text_style = |size: f32| TextStyle {
+        font: font.clone(),
+        font_size: size,
+        color: Color::WHITE,
+    };
+
+    // Like the new map dialog, the backdrop's `Interaction` keeps clicks from
+    // reaching the tiles underneath.
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+                z_index: ZIndex::Global(20),
+                ..Default::default()
+            },
+            Interaction::default(),
+            SettingsPanel,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    background_color: BackgroundColor(Color::DARK_GRAY),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Click a binding, then press a key, mouse or gamepad button. \
+                         Delete clears it, Escape cancels.",
+                        text_style(14.0),
+                    ));
+                    parent.spawn((
+                        TextBundle::from_section("", text_style(14.0)).with_style(Style {
+                            margin: UiRect::all(Val::Px(5.0)),
+                            ..Default::default()
+                        }),
+                        SettingsMessage,
+                    ));
+
+                    // Two columns of rows so every action fits on screen.
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                flex_wrap: FlexWrap::Wrap,
+                                max_height: Val::Vh(70.0),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            for entry in ActionMap::default().actions() {
+                                spawn_binding_row(parent, &text_style(14.0), entry.action);
+                            }
+                        });
+
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            spawn_button(
+                                parent,
+                                &font,
+                                "Defaults",
+                                Color::SILVER,
+                                SettingsControl::ResetDefaults,
+                            );
+                            spawn_button(
+                                parent,
+                                &font,
+                                "Close",
+                                Color::SILVER,
+                                SettingsControl::Close,
+                            );
+                        });
+                });
+        });
+}
+
+fn spawn_binding_row(parent: &mut ChildBuilder, text_style: &TextStyle, action: Action) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                margin: UiRect::horizontal(Val::Px(10.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(action.label(), text_style.clone()).with_style(Style {
+                    width: Val::Px(140.0),
+                    ..Default::default()
+                }),
+            );
+            for slot in 0..SLOTS {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(110.0),
+                                height: Val::Px(24.0),
+                                margin: UiRect::all(Val::Px(2.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: BackgroundColor(SLOT_COLOR),
+                            ..Default::default()
+                        },
+                        BindingSlot { action, slot },
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                color: Color::BLACK,
+                                ..text_style.clone()
+                            },
+                        ));
+                    });
+            }
+        });
+}
+
+/// Toggles the screen from the tool bar button or [`Action::OpenSettings`].
+pub fn settings_toggle_system(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<SettingsButton>)>,
+    mut events: EventReader<ActionEvent>,
+    mut screen: ResMut<SettingsScreen>,
+) {
+    let clicked = interaction_query
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed);
+    let shortcut = events
+        .read()
+        .any(|ActionEvent(action)| *action == Action::OpenSettings);
+    if clicked || shortcut {
+        screen.open = !screen.open;
+        screen.capturing = None;
+    }
+}
+
+/// Waits for the next button press while a slot is being rebound and
+/// applies it, warning about other actions on the same button. Runs before
+/// the slot buttons react, so the click that started the capture is not
+/// captured itself.
+pub fn binding_capture_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepad: Res<ButtonInput<GamepadButton>>,
+    path: Res<BindingsPath>,
+    mut screen: ResMut<SettingsScreen>,
+    mut actions: ResMut<ActionMap>,
+) {
+    if !screen.open {
+        return;
+    }
+    let Some((action, slot)) = screen.capturing else {
+        if keys.just_pressed(KeyCode::Escape) {
+            screen.open = false;
+        }
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::Escape) {
+        screen.capturing = None;
+        return;
+    }
+    if keys.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) {
+        actions.unbind(action, slot);
+        screen.capturing = None;
+        screen.message = format!("Cleared a binding of {}", action.label());
+        save_bindings(&actions, &path);
+        return;
+    }
+
+    let modifiers = [
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::ShiftLeft,
+        KeyCode::ShiftRight,
+    ];
+    let button = keys
+        .get_just_pressed()
+        .find(|key| !modifiers.contains(key))
+        .map(|key| InputButton::Key(*key))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|b| InputButton::Mouse(*b))
+        })
+        .or_else(|| {
+            gamepad
+                .get_just_pressed()
+                .next()
+                .map(|b| InputButton::Gamepad(b.button_type))
+        });
+    let Some(button) = button else {
+        return;
+    };
+
+    let binding = Binding {
+        button,
+        ctrl: keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]),
+        shift: keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+    };
+    let conflicts = actions.conflicts(action, &binding);
+    actions.rebind(action, slot, binding);
+    screen.capturing = None;
+    screen.message = if conflicts.is_empty() {
+        format!("{} is now bound to {}", action.label(), binding.label())
+    } else {
+        let others: Vec<String> = conflicts.iter().map(Action::label).collect();
+        format!(
+            "Conflict: {} is also bound to {}",
+            binding.label(),
+            others.join(", ")
+        )
+    };
+    save_bindings(&actions, &path);
+}
+
+fn save_bindings(actions: &ActionMap, path: &BindingsPath) {
+    if let Err(err) = actions.save(&path.0) {
+        error!("failed to save bindings to {}: {err}", path.0.display());
+    }
+}
+
+pub fn settings_button_system(
+    slots: Query<(&Interaction, &BindingSlot), Changed<Interaction>>,
+    controls: Query<(&Interaction, &SettingsControl), Changed<Interaction>>,
+    path: Res<BindingsPath>,
+    mut screen: ResMut<SettingsScreen>,
+    mut actions: ResMut<ActionMap>,
+) {
+    for (interaction, slot) in &slots {
+        if *interaction == Interaction::Pressed {
+            screen.capturing = Some((slot.action, slot.slot));
+        }
+    }
+    for (interaction, control) in &controls {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match control {
+            SettingsControl::ResetDefaults => {
+                *actions = ActionMap::default();
+                screen.capturing = None;
+                screen.message = "Restored the default bindings".to_string();
+                save_bindings(&actions, &path);
+            }
+            SettingsControl::Close => {
+                screen.open = false;
+                screen.capturing = None;
+            }
+        }
+    }
+}
+
+/// Stops input from triggering actions while a binding is being captured.
+pub fn settings_suspend_system(
+    screen: Res<SettingsScreen>,
+    mut suspended: ResMut<ActionsSuspended>,
+) {
+    let capturing = screen.capturing.is_some();
+    if suspended.0 != capturing {
+        suspended.0 = capturing;
+    }
+}
+
+/// Shows the screen and refreshes every slot, marking the one being
+/// captured and any binding shared with another action.
+pub fn settings_text_system(
+    screen: Res<SettingsScreen>,
+    actions: Res<ActionMap>,
+    mut panels: Query<&mut Style, With<SettingsPanel>>,
+    mut slots: Query<(&BindingSlot, &Children, &mut BackgroundColor)>,
+    mut texts: Query<&mut Text>,
+    mut messages: Query<Entity, With<SettingsMessage>>,
+) {
+    if !screen.is_changed() && !actions.is_changed() {
+        return;
+    }
+    for mut style in &mut panels {
+        style.display = if screen.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    for (slot, children, mut background) in &mut slots {
+        let binding = actions.bindings(slot.action).get(slot.slot);
+        let capturing = screen.capturing == Some((slot.action, slot.slot));
+        let label = match binding {
+            _ if capturing => "Press...".to_string(),
+            Some(binding) => binding.label(),
+            None => "-".to_string(),
+        };
+        let conflicting =
+            binding.is_some_and(|binding| !actions.conflicts(slot.action, binding).is_empty());
+        background.0 = if capturing {
+            CAPTURE_COLOR
+        } else if conflicting {
+            CONFLICT_COLOR
+        } else {
+            SLOT_COLOR
+        };
+        for child in children {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+
+    for entity in &mut messages {
+        if let Ok(mut text) = texts.get_mut(entity) {
+            text.sections[0].value = screen.message.clone();
+        }
+    }
+}
@@ -0,0 +1,700 @@
+use std::collections::{BTreeMap, HashMap};
+use std::{fs, path::Path};
+
+use bevy::prelude::Resource;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::TileType;
+use crate::crops::{CropGrowth, GrowthStage};
+use crate::generation::{TerrainParams, generate_tile};
+use crate::grid::{GridConfig, MAX_GRID_SIZE};
+use crate::history::TileState;
+use crate::map::{MAP_FORMAT_VERSION, MapFile, MapTile};
+
+/// Maps saved with this extension are Tiled JSON maps.
+const TILED_EXTENSION: &str = "tmj";
+
+/// Pixel size given to tiles in exported maps; Tiled needs one to draw the
+/// grid.
+const EXPORT_TILE_SIZE: u32 = 32;
+
+/// Gids keep the flip and rotation flags in their top bits.
+const GID_MASK: u32 = 0x0fff_ffff;
+
+/// Tile properties read and written alongside the tile type.
+const TYPE_PROPERTY: &str = "tile_type";
+const STAGE_PROPERTY: &str = "stage";
+const BED_PROPERTY: &str = "bed";
+const MOISTURE_PROPERTY: &str = "moisture";
+
+/// Moisture is written in steps of 1/20, so a garden needs a handful of
+/// tileset tiles rather than one per watered tile.
+const MOISTURE_STEPS: f32 = 20.0;
+
+pub fn is_tiled_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == TILED_EXTENSION)
+}
+
+/// Which tile of a Tiled tileset stands for which tile type, by tile id
+/// within the tileset. Tiles with a `tile_type` property use that instead,
+/// so exported maps describe themselves.
+///
+/// ```json
+/// { "0": "Grass", "1": "Dirt", "2": "Water", "3": "Crop", "4": "Chinampa" }
+/// ```
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TiledTable {
+    types: BTreeMap<u32, TileType>,
+}
+
+impl Default for TiledTable {
+    /// The first tiles of the tileset, in the order of the tile buttons.
+    fn default() -> Self {
+        TiledTable {
+            types: (0..).zip(TileType::ALL).collect(),
+        }
+    }
+}
+
+impl TiledTable {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        serde_json::from_str(&contents).map_err(|err| err.to_string())
+    }
+
+    /// First tile id standing for `tile_type`.
+    fn id_for(&self, tile_type: TileType) -> Option<u32> {
+        self.types
+            .iter()
+            .find(|(_, t)| **t == tile_type)
+            .map(|(id, _)| *id)
+    }
+}
+
+/// The parts of a Tiled JSON map the garden reads and writes.
+#[derive(Serialize, Deserialize, Debug)]
+struct TiledMap {
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    infinite: bool,
+    layers: Vec<TiledLayer>,
+    tilesets: Vec<TiledTileset>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<TiledProperty>,
+    /// Fields Tiled expects but the garden does not use.
+    #[serde(flatten)]
+    other: serde_json::Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TiledLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TiledTileset {
+    firstgid: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TiledTile>,
+    #[serde(flatten)]
+    other: serde_json::Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TiledTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TiledProperty {
+    name: String,
+    #[serde(rename = "type", default = "string_type")]
+    kind: String,
+    value: Value,
+}
+
+fn string_type() -> String {
+    "string".to_string()
+}
+
+impl TiledProperty {
+    fn string(name: &str, value: impl ToString) -> Self {
+        TiledProperty {
+            name: name.to_string(),
+            kind: string_type(),
+            value: Value::String(value.to_string()),
+        }
+    }
+
+    fn float(name: &str, value: f32) -> Self {
+        TiledProperty {
+            name: name.to_string(),
+            kind: "float".to_string(),
+            // Through the shortest decimal, so 0.35 is not written as
+            // 0.3499999940395355.
+            value: json!(value.to_string().parse::<f64>().unwrap_or_default()),
+        }
+    }
+}
+
+fn property<'a>(properties: &'a [TiledProperty], name: &str) -> Option<&'a Value> {
+    properties
+        .iter()
+        .find(|property| property.name == name)
+        .map(|property| &property.value)
+}
+
+/// A tile type together with the custom properties a tileset tile carries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TileInfo {
+    tile_type: TileType,
+    stage: Option<GrowthStage>,
+    bed: Option<TileType>,
+    moisture: Option<f32>,
+}
+
+impl TileInfo {
+    fn plain(tile_type: TileType) -> Self {
+        TileInfo {
+            tile_type,
+            stage: None,
+            bed: None,
+            moisture: None,
+        }
+    }
+
+    fn from_tile(tile: &MapTile) -> Self {
+        TileInfo {
+            tile_type: tile.tile_type,
+            stage: tile.stage,
+            bed: tile.bed,
+            // Rounded down, so tiles stay on the same side of the dry
+            // threshold; the slack absorbs levels like 0.35 that are stored
+            // just below their step.
+            moisture: tile
+                .moisture
+                .map(|level| (level * MOISTURE_STEPS + 1e-4).floor() / MOISTURE_STEPS),
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        self.stage.is_none() && self.bed.is_none() && self.moisture.is_none()
+    }
+
+    fn properties(&self) -> Vec<TiledProperty> {
+        let mut properties = vec![TiledProperty::string(
+            TYPE_PROPERTY,
+            format!("{:?}", self.tile_type),
+        )];
+        if let Some(stage) = self.stage {
+            properties.push(TiledProperty::string(STAGE_PROPERTY, format!("{stage:?}")));
+        }
+        if let Some(bed) = self.bed {
+            properties.push(TiledProperty::string(BED_PROPERTY, format!("{bed:?}")));
+        }
+        if let Some(moisture) = self.moisture {
+            properties.push(TiledProperty::float(MOISTURE_PROPERTY, moisture));
+        }
+        properties
+    }
+
+    /// Reads a tileset tile, taking its type from its `tile_type` property
+    /// or, failing that, from `table`.
+    fn read(id: u32, properties: &[TiledProperty], table: &TiledTable) -> Result<Self, String> {
+        let tile_type = match property(properties, TYPE_PROPERTY) {
+            Some(Value::String(name)) => name.parse()?,
+            Some(other) => return Err(format!("tile {id}: invalid tile_type {other}")),
+            None => *table
+                .types
+                .get(&id)
+                .ok_or_else(|| format!("tile {id} has no tile type"))?,
+        };
+        let stage = property(properties, STAGE_PROPERTY)
+            .map(|value| serde_json::from_value::<GrowthStage>(value.clone()))
+            .transpose()
+            .map_err(|err| format!("tile {id}: invalid stage: {err}"))?;
+        let bed = match property(properties, BED_PROPERTY) {
+            Some(Value::String(name)) => Some(name.parse()?),
+            Some(other) => return Err(format!("tile {id}: invalid bed {other}")),
+            None => None,
+        };
+        let moisture = match property(properties, MOISTURE_PROPERTY) {
+            Some(value) => Some(
+                value
+                    .as_f64()
+                    .filter(|level| (0.0..=1.0).contains(level))
+                    .ok_or_else(|| format!("tile {id}: moisture must be between 0 and 1"))?
+                    as f32,
+            ),
+            None => None,
+        };
+        Ok(TileInfo {
+            tile_type,
+            stage,
+            bed,
+            moisture,
+        })
+    }
+}
+
+/// Reads a Tiled JSON map with embedded tilesets and uncompressed tile
+/// layers. Layers are stacked in order, so a tile in a later layer covers
+/// the ones below it. The `seed` map property and the terrain properties
+/// round-trip like the fields of [`MapFile`].
+pub fn parse_tiled(contents: &str, table: &TiledTable) -> Result<MapFile, String> {
+    let tiled: TiledMap = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+    if tiled.infinite {
+        return Err("infinite Tiled maps are not supported".to_string());
+    }
+    // Checked before the cells are allocated; `validate` comes too late.
+    let valid_size = 1..=MAX_GRID_SIZE;
+    if !valid_size.contains(&tiled.width) || !valid_size.contains(&tiled.height) {
+        return Err(format!(
+            "map is {}x{} but must be between 1x1 and {MAX_GRID_SIZE}x{MAX_GRID_SIZE}",
+            tiled.width, tiled.height
+        ));
+    }
+
+    let mut tilesets = Vec::new();
+    for tileset in &tiled.tilesets {
+        if let Some(source) = &tileset.source {
+            return Err(format!(
+                "external tileset {source} is not supported; embed it in the map"
+            ));
+        }
+        let mut tiles: HashMap<u32, TileInfo> = table
+            .types
+            .iter()
+            .map(|(id, tile_type)| (*id, TileInfo::plain(*tile_type)))
+            .collect();
+        for tile in &tileset.tiles {
+            let info = TileInfo::read(tile.id, &tile.properties, table)
+                .map_err(|err| format!("tileset {}: {err}", tileset.name))?;
+            tiles.insert(tile.id, info);
+        }
+        tilesets.push((tileset.firstgid, tileset.name.as_str(), tiles));
+    }
+    tilesets.sort_by_key(|(firstgid, _, _)| *firstgid);
+    let lookup = |gid: u32| -> Result<TileInfo, String> {
+        let (firstgid, name, tiles) = tilesets
+            .iter()
+            .rev()
+            .find(|(firstgid, _, _)| *firstgid <= gid)
+            .ok_or_else(|| format!("tile {gid} is not in any tileset"))?;
+        let id = gid - firstgid;
+        tiles
+            .get(&id)
+            .copied()
+            .ok_or_else(|| format!("tileset {name}: tile {id} has no tile type"))
+    };
+
+    let cell_count = (tiled.width * tiled.height) as usize;
+    let mut cells: Vec<Option<TileInfo>> = vec![None; cell_count];
+    for layer in tiled
+        .layers
+        .iter()
+        .filter(|layer| layer.kind == "tilelayer")
+    {
+        if let Some(encoding) = layer.encoding.as_deref().filter(|e| *e != "csv") {
+            return Err(format!(
+                "layer {}: {encoding} encoding is not supported; save with CSV layer format",
+                layer.name
+            ));
+        }
+        let data: Vec<u32> = match &layer.data {
+            Some(data) => serde_json::from_value(data.clone())
+                .map_err(|err| format!("layer {}: {err}", layer.name))?,
+            None => continue,
+        };
+        if data.len() != cell_count {
+            return Err(format!(
+                "layer {} has {} tiles but the map is {}x{}",
+                layer.name,
+                data.len(),
+                tiled.width,
+                tiled.height
+            ));
+        }
+        for (cell, gid) in cells.iter_mut().zip(data) {
+            let gid = gid & GID_MASK;
+            if gid != 0 {
+                *cell = Some(lookup(gid)?);
+            }
+        }
+    }
+
+    let seed = match property(&tiled.properties, "seed") {
+        Some(Value::String(seed)) => Some(
+            seed.parse()
+                .map_err(|_| format!("invalid seed property `{seed}`"))?,
+        ),
+        Some(Value::Number(seed)) => Some(
+            seed.as_u64()
+                .ok_or_else(|| format!("invalid seed property {seed}"))?,
+        ),
+        Some(other) => return Err(format!("invalid seed property {other}")),
+        None => None,
+    };
+    let terrain = seed.map(|_| read_terrain(&tiled.properties));
+
+    // Tiled rows run top to bottom, world y bottom to top.
+    let mut tiles: Vec<MapTile> = cells
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, cell)| {
+            let info = cell?;
+            let x = index as u32 % tiled.width;
+            let y = tiled.height - 1 - index as u32 / tiled.width;
+            let state = TileState {
+                tile_type: info.tile_type,
+                growth: info
+                    .stage
+                    .map(|stage| CropGrowth::new(stage, info.bed.unwrap_or(TileType::Dirt))),
+            };
+            Some(MapTile::new(x, y, state).with_moisture(info.moisture))
+        })
+        .collect();
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+
+    Ok(MapFile {
+        version: MAP_FORMAT_VERSION,
+        width: tiled.width,
+        height: tiled.height,
+        seed,
+        terrain,
+        tiles,
+    })
+}
+
+/// Terrain settings from map properties, with defaults for missing ones.
+fn read_terrain(properties: &[TiledProperty]) -> TerrainParams {
+    let mut terrain = TerrainParams::default();
+    for (name, field) in [
+        ("water_level", &mut terrain.water_level),
+        ("roughness", &mut terrain.roughness),
+        ("crop_density", &mut terrain.crop_density),
+        ("feature_size", &mut terrain.feature_size),
+    ] {
+        if let Some(value) = property(properties, name).and_then(Value::as_f64) {
+            *field = value as f32;
+        }
+    }
+    terrain
+}
+
+/// Writes `map` as a single tile layer over one embedded tileset. Tiles of
+/// each type share the tile `table` gives them; crop stages, beds and
+/// moisture get a tileset tile of their own carrying them as properties,
+/// with moisture rounded down to steps of 0.05.
+/// The tileset has no image, so one can be assigned in Tiled.
+pub fn to_tiled(map: &MapFile, table: &TiledTable) -> Result<String, String> {
+    let listed: HashMap<(u32, u32), &MapTile> = map
+        .tiles
+        .iter()
+        .map(|tile| ((tile.x, tile.y), tile))
+        .collect();
+    let grid = GridConfig {
+        width: map.width,
+        height: map.height,
+        ..Default::default()
+    };
+    let terrain = map.terrain.unwrap_or_default();
+
+    let mut next_id = table.types.keys().max().map_or(0, |id| id + 1);
+    let mut ids: Vec<(TileInfo, u32)> = Vec::new();
+    let mut id_for = |info: TileInfo| {
+        if let Some((_, id)) = ids.iter().find(|(known, _)| *known == info) {
+            return *id;
+        }
+        let id = match table.id_for(info.tile_type) {
+            Some(id) if info.is_plain() => id,
+            _ => {
+                next_id += 1;
+                next_id - 1
+            }
+        };
+        ids.push((info, id));
+        id
+    };
+
+    let mut data = Vec::with_capacity((map.width * map.height) as usize);
+    for y in (0..map.height).rev() {
+        for x in 0..map.width {
+            let info = match (listed.get(&(x, y)), map.seed) {
+                (Some(tile), _) => TileInfo::from_tile(tile),
+                (None, Some(seed)) => TileInfo::plain(generate_tile(&grid, seed, &terrain, x, y)),
+                (None, None) => {
+                    return Err(format!("tile ({x}, {y}) is missing from the map"));
+                }
+            };
+            data.push(id_for(info) + 1);
+        }
+    }
+
+    let mut tiles: Vec<TiledTile> = ids
+        .iter()
+        .map(|(info, id)| TiledTile {
+            id: *id,
+            properties: info.properties(),
+        })
+        .collect();
+    tiles.sort_by_key(|tile| tile.id);
+    let tile_count = tiles.last().map_or(0, |tile| tile.id + 1);
+
+    let mut properties = Vec::new();
+    if let Some(seed) = map.seed {
+        properties.push(TiledProperty::string("seed", seed));
+        properties.extend([
+            TiledProperty::float("water_level", terrain.water_level),
+            TiledProperty::float("roughness", terrain.roughness),
+            TiledProperty::float("crop_density", terrain.crop_density),
+            TiledProperty::float("feature_size", terrain.feature_size),
+        ]);
+    }
+
+    let object = |value: Value| match value {
+        Value::Object(object) => object,
+        _ => unreachable!("json! objects are objects"),
+    };
+    let tiled = TiledMap {
+        width: map.width,
+        height: map.height,
+        infinite: false,
+        layers: vec![TiledLayer {
+            kind: "tilelayer".to_string(),
+            name: "garden".to_string(),
+            width: Some(map.width),
+            height: Some(map.height),
+            data: Some(json!(data)),
+            encoding: None,
+            other: object(json!({
+                "id": 1, "x": 0, "y": 0, "opacity": 1, "visible": true,
+            })),
+        }],
+        tilesets: vec![TiledTileset {
+            firstgid: 1,
+            name: "garden".to_string(),
+            source: None,
+            tiles,
+            other: object(json!({
+                "tilewidth": EXPORT_TILE_SIZE,
+                "tileheight": EXPORT_TILE_SIZE,
+                "tilecount": tile_count,
+                "columns": 0,
+                "margin": 0,
+                "spacing": 0,
+            })),
+        }],
+        properties,
+        other: object(json!({
+            "type": "map",
+            "version": "1.10",
+            "orientation": "orthogonal",
+            "renderorder": "right-down",
+            "tilewidth": EXPORT_TILE_SIZE,
+            "tileheight": EXPORT_TILE_SIZE,
+            "nextlayerid": 2,
+            "nextobjectid": 1,
+        })),
+    };
+    serde_json::to_string_pretty(&tiled).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tileset(json: &str) -> TiledTileset {
+        let tiled: TiledMap = serde_json::from_str(json).unwrap();
+        tiled.tilesets.into_iter().next().unwrap()
+    }
+
+    /// A 3x2 map as Tiled writes it, with the tileset typed by the table.
+    fn tiled_map(layers: &str) -> String {
+        format!(
+            r#"{{
+                "width": 3, "height": 2, "infinite": false,
+                "tilewidth": 16, "tileheight": 16,
+                "layers": [{layers}],
+                "tilesets": [{{ "firstgid": 1, "name": "garden", "tiles": [] }}]
+            }}"#
+        )
+    }
+
+    #[test]
+    fn reads_tiles_through_the_table() {
+        let json = tiled_map(r#"{ "type": "tilelayer", "data": [3, 3, 1, 2, 4, 5] }"#);
+        let map = parse_tiled(&json, &TiledTable::default()).unwrap();
+        assert_eq!(map.type_at(0, 1), TileType::Water);
+        assert_eq!(map.type_at(2, 1), TileType::Grass);
+        assert_eq!(map.type_at(0, 0), TileType::Dirt);
+        assert_eq!(map.type_at(1, 0), TileType::Crop);
+        assert_eq!(map.type_at(2, 0), TileType::Chinampa);
+        assert!(map.validate().is_ok());
+
+        let table: TiledTable = serde_json::from_str(r#"{ "0": "Water", "2": "Grass" }"#).unwrap();
+        let json = tiled_map(r#"{ "type": "tilelayer", "data": [1, 3, 1, 1, 3, 1] }"#);
+        let map = parse_tiled(&json, &table).unwrap();
+        assert_eq!(map.type_at(0, 0), TileType::Water);
+        assert_eq!(map.type_at(1, 0), TileType::Grass);
+    }
+
+    #[test]
+    fn later_layers_cover_earlier_ones() {
+        // The second layer is empty except for a flipped Water tile.
+        let flipped_water = 3 | 0x8000_0000u32;
+        let json = tiled_map(&format!(
+            r#"{{ "type": "tilelayer", "data": [1, 1, 1, 1, 1, 1] }},
+               {{ "type": "objectgroup", "objects": [] }},
+               {{ "type": "tilelayer", "data": [0, {flipped_water}, 0, 0, 0, 0] }}"#
+        ));
+        let map = parse_tiled(&json, &TiledTable::default()).unwrap();
+        assert_eq!(map.type_at(0, 1), TileType::Grass);
+        assert_eq!(map.type_at(1, 1), TileType::Water);
+    }
+
+    #[test]
+    fn saving_round_trips_tile_properties() {
+        let mut map = parse_tiled(
+            &tiled_map(r#"{ "type": "tilelayer", "data": [3, 3, 1, 2, 4, 5] }"#),
+            &TiledTable::default(),
+        )
+        .unwrap();
+        map.tiles[1] = MapTile::new(
+            1,
+            0,
+            TileState {
+                tile_type: TileType::Crop,
+                growth: Some(CropGrowth::new(GrowthStage::Mature, TileType::Chinampa)),
+            },
+        )
+        .with_moisture(Some(0.35));
+
+        let json = to_tiled(&map, &TiledTable::default()).unwrap();
+        let reloaded = parse_tiled(&json, &TiledTable::default()).unwrap();
+        let crop = reloaded.tiles[1];
+        assert_eq!(crop.stage, Some(GrowthStage::Mature));
+        assert_eq!(crop.bed, Some(TileType::Chinampa));
+        assert_eq!(crop.moisture, Some(0.35));
+        assert_eq!(to_tiled(&reloaded, &TiledTable::default()).unwrap(), json);
+    }
+
+    #[test]
+    fn unsupported_maps_are_reported() {
+        let table = TiledTable::default();
+        let short = tiled_map(r#"{ "type": "tilelayer", "name": "ground", "data": [1, 1] }"#);
+        assert_eq!(
+            parse_tiled(&short, &table).unwrap_err(),
+            "layer ground has 2 tiles but the map is 3x2"
+        );
+        let encoded = tiled_map(r#"{ "type": "tilelayer", "encoding": "base64", "data": "AQ==" }"#);
+        assert!(parse_tiled(&encoded, &table).is_err());
+        let unknown = tiled_map(r#"{ "type": "tilelayer", "data": [9, 1, 1, 1, 1, 1] }"#);
+        assert!(parse_tiled(&unknown, &table).is_err());
+
+        let huge = r#"{ "width": 70000, "height": 70000, "layers": [], "tilesets": [] }"#;
+        assert!(
+            parse_tiled(huge, &table)
+                .unwrap_err()
+                .starts_with("map is 70000x70000")
+        );
+        let empty = r#"{ "width": 0, "height": 4, "layers": [], "tilesets": [] }"#;
+        assert!(parse_tiled(empty, &table).is_err());
+    }
+
+    #[test]
+    fn moisture_levels_share_tileset_tiles() {
+        let tiles: Vec<MapTile> = (0..40)
+            .flat_map(|y| (0..40).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let state = TileState {
+                    tile_type: TileType::Dirt,
+                    growth: None,
+                };
+                let level = (x * 40 + y) as f32 / 1600.0;
+                MapTile::new(x, y, state).with_moisture(Some(level))
+            })
+            .collect();
+        let map = MapFile {
+            version: MAP_FORMAT_VERSION,
+            width: 40,
+            height: 40,
+            seed: None,
+            terrain: None,
+            tiles,
+        };
+
+        let json = to_tiled(&map, &TiledTable::default()).unwrap();
+        assert_eq!(tileset(&json).tiles.len(), MOISTURE_STEPS as usize);
+        let reloaded = parse_tiled(&json, &TiledTable::default()).unwrap();
+        for (saved, loaded) in map.tiles.iter().zip(&reloaded.tiles) {
+            let (saved, loaded) = (saved.moisture.unwrap(), loaded.moisture.unwrap());
+            assert!(
+                loaded <= saved && saved - loaded < 0.05,
+                "{saved} -> {loaded}"
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_tiles_use_the_table_and_keep_the_seed() {
+        let terrain = TerrainParams {
+            water_level: 0.4,
+            ..Default::default()
+        };
+        let map = MapFile {
+            version: MAP_FORMAT_VERSION,
+            width: 6,
+            height: 5,
+            seed: Some(7),
+            terrain: Some(terrain),
+            tiles: Vec::new(),
+        };
+        let grid = GridConfig {
+            width: 6,
+            height: 5,
+            ..Default::default()
+        };
+
+        // Generated tiles carry no properties, so they are the table's own.
+        let json = to_tiled(&map, &TiledTable::default()).unwrap();
+        assert!(
+            tileset(&json)
+                .tiles
+                .iter()
+                .all(|tile| tile.id < TileType::ALL.len() as u32)
+        );
+
+        let reloaded = parse_tiled(&json, &TiledTable::default()).unwrap();
+        assert_eq!(reloaded.seed, Some(7));
+        assert_eq!(reloaded.terrain.unwrap().water_level, 0.4);
+        for y in 0..5 {
+            for x in 0..6 {
+                let expected = generate_tile(&grid, 7, &terrain, x, y);
+                assert_eq!(reloaded.type_at(x, y), expected, "tile ({x}, {y})");
+            }
+        }
+    }
+}
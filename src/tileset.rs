@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::{fs, path::Path, path::PathBuf};
+
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::autotile::AutotileMask;
+use crate::crops::{CropGrowth, GrowthStage, tile_color};
+use crate::{Tile, TilePosition, TileType};
+
+/// Where the tileset manifest is looked for when no `--tileset` is given.
+pub const DEFAULT_TILESET_PATH: &str = "assets/tileset.json";
+
+/// Describes a sprite sheet of equally sized frames and which of them each
+/// tile type may be drawn with.
+///
+/// ```json
+/// {
+///   "image": "textures/tiles.png",
+///   "frame_size": 32,
+///   "columns": 4,
+///   "rows": 3,
+///   "frames": { "Grass": [0, 1, 2], "Dirt": [4], "Water": [8, 9] },
+///   "crop_stages": { "Seeded": [5], "Mature": [6] },
+///   "edges": { "Water": [10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 8] }
+/// }
+/// ```
+///
+/// Frames are numbered row by row from the top left. Tile types, and crop
+/// stages, without frames keep their flat colour.
+///
+/// `edges` lists 16 frames for a tile type, indexed by its [`AutotileMask`]:
+/// bit 1 set when the tile to the north is of the same type, 2 east, 4 south
+/// and 8 west. Tiles with all four set are interior and use `frames`, so
+/// only shorelines and borders come from this table.
+#[derive(Deserialize, Debug)]
+pub struct TilesetManifest {
+    /// Sprite sheet, relative to the asset folder.
+    pub image: String,
+    /// Width and height of one frame in pixels.
+    pub frame_size: u32,
+    pub columns: usize,
+    pub rows: usize,
+    #[serde(default)]
+    pub frames: HashMap<TileType, Vec<usize>>,
+    /// Frames for crops in each growth stage, used for `Crop` tiles instead
+    /// of `frames`.
+    #[serde(default)]
+    pub crop_stages: HashMap<GrowthStage, Vec<usize>>,
+    /// Edge and corner frames for tile types that blend into their
+    /// neighbours.
+    #[serde(default)]
+    pub edges: HashMap<TileType, [usize; 16]>,
+}
+
+impl TilesetManifest {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let manifest: TilesetManifest =
+            serde_json::from_str(&contents).map_err(|err| err.to_string())?;
+
+        let frame_count = manifest.columns * manifest.rows;
+        let all_frames = manifest
+            .frames
+            .values()
+            .chain(manifest.crop_stages.values())
+            .flatten()
+            .chain(manifest.edges.values().flatten());
+        if let Some(frame) = all_frames.copied().find(|frame| *frame >= frame_count) {
+            return Err(format!(
+                "frame {frame} is outside the {}x{} sheet",
+                manifest.columns, manifest.rows
+            ));
+        }
+        Ok(manifest)
+    }
+}
+
+/// Tileset manifest chosen with `--tileset`.
+#[derive(Resource)]
+pub struct TilesetPath {
+    pub path: PathBuf,
+    /// Whether the path was given explicitly, so a missing file is worth a
+    /// warning rather than a note.
+    pub explicit: bool,
+}
+
+#[derive(Clone)]
+pub struct TileAtlas {
+    image: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+    frames: HashMap<TileType, Vec<usize>>,
+    crop_stages: HashMap<GrowthStage, Vec<usize>>,
+    edges: HashMap<TileType, [usize; 16]>,
+}
+
+/// How tiles are drawn.
+#[derive(Resource, Default)]
+pub enum Tileset {
+    /// No usable atlas; every tile is drawn with its flat colour.
+    #[default]
+    Flat,
+    /// Waiting for the sprite sheet to finish loading.
+    Loading(TileAtlas),
+    Ready(TileAtlas),
+}
+
+impl Tileset {
+    /// Atlas frame for a tile, or `None` when it is drawn as a flat colour.
+    fn frame(
+        &self,
+        pos: &TilePosition,
+        tile_type: &TileType,
+        growth: Option<&CropGrowth>,
+        mask: AutotileMask,
+    ) -> Option<(&TileAtlas, usize)> {
+        let Tileset::Ready(atlas) = self else {
+            return None;
+        };
+        if let Some(edges) = atlas.edges.get(tile_type)
+            && !mask.is_interior()
+        {
+            return Some((atlas, edges[mask.0 as usize]));
+        }
+        let frames = match growth {
+            Some(growth) if *tile_type == TileType::Crop => atlas.crop_stages.get(&growth.stage),
+            _ => atlas.frames.get(tile_type),
+        }?;
+        let variant = variant_index(pos.x, pos.y, frames.len())?;
+        Some((atlas, frames[variant]))
+    }
+}
+
+/// Picks one of `count` variants for the tile at `(x, y)`. Scattered like a
+/// random pick but stable, so a tile looks the same every time its chunk is
+/// streamed back in.
+fn variant_index(x: u32, y: u32, count: usize) -> Option<usize> {
+    if count == 0 {
+        return None;
+    }
+    let mut hash = ((x as u64) << 32) | y as u64;
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^= hash >> 33;
+    Some((hash % count as u64) as usize)
+}
+
+/// Reads the tileset manifest and starts loading its sprite sheet. Without a
+/// usable manifest the garden keeps its flat colours.
+pub fn setup_tileset(
+    mut commands: Commands,
+    path: Res<TilesetPath>,
+    asset_server: Res<AssetServer>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let manifest = match TilesetManifest::load(&path.path) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            let missing = fs::metadata(&path.path)
+                .err()
+                .is_some_and(|err| err.kind() == ErrorKind::NotFound);
+            if missing && !path.explicit {
+                info!("no tileset at {}, using flat colours", path.path.display());
+            } else {
+                warn!(
+                    "could not load tileset {}: {err}; using flat colours",
+                    path.path.display()
+                );
+            }
+            return;
+        }
+    };
+
+    let layout = TextureAtlasLayout::from_grid(
+        Vec2::splat(manifest.frame_size as f32),
+        manifest.columns,
+        manifest.rows,
+        None,
+        None,
+    );
+    commands.insert_resource(Tileset::Loading(TileAtlas {
+        image: asset_server.load(manifest.image),
+        layout: layouts.add(layout),
+        frames: manifest.frames,
+        crop_stages: manifest.crop_stages,
+        edges: manifest.edges,
+    }));
+}
+
+/// Switches to the atlas once its image has loaded, or back to flat colours
+/// if it cannot be loaded.
+pub fn tileset_load_system(asset_server: Res<AssetServer>, mut tileset: ResMut<Tileset>) {
+    let Tileset::Loading(atlas) = &*tileset else {
+        return;
+    };
+    match asset_server.load_state(&atlas.image) {
+        LoadState::Loaded => *tileset = Tileset::Ready(atlas.clone()),
+        LoadState::Failed => {
+            warn!("tileset image failed to load; using flat colours");
+            *tileset = Tileset::Flat;
+        }
+        LoadState::NotLoaded | LoadState::Loading => {}
+    }
+}
+
+/// Keeps every tile's sprite in step with its type, crop stage and edges, and
+/// redraws the whole garden when the tileset changes.
+pub fn tile_appearance_system(
+    mut commands: Commands,
+    tileset: Res<Tileset>,
+    mut tiles: Query<
+        (
+            Entity,
+            &TilePosition,
+            Ref<TileType>,
+            Option<Ref<CropGrowth>>,
+            Ref<AutotileMask>,
+            &mut Sprite,
+            &mut Handle<Image>,
+            Option<&mut TextureAtlas>,
+        ),
+        With<Tile>,
+    >,
+) {
+    let redraw_all = tileset.is_changed();
+    for (entity, pos, tile_type, growth, mask, mut sprite, mut texture, texture_atlas) in &mut tiles
+    {
+        let growth_changed = growth.as_ref().is_some_and(|growth| growth.is_changed());
+        if !redraw_all && !tile_type.is_changed() && !growth_changed && !mask.is_changed() {
+            continue;
+        }
+
+        let growth = growth.as_deref();
+        match tileset.frame(pos, &tile_type, growth, *mask) {
+            Some((atlas, index)) => {
+                sprite.color = Color::WHITE;
+                if *texture != atlas.image {
+                    *texture = atlas.image.clone();
+                }
+                match texture_atlas {
+                    Some(mut texture_atlas) => texture_atlas.index = index,
+                    None => {
+                        commands.entity(entity).insert(TextureAtlas {
+                            layout: atlas.layout.clone(),
+                            index,
+                        });
+                    }
+                }
+            }
+            None => {
+                sprite.color = tile_color(&tile_type, growth);
+                if texture_atlas.is_some() {
+                    commands.entity(entity).remove::<TextureAtlas>();
+                    *texture = Handle::default();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::grid::{GridConfig, TileGrid, TypeMap};
+use crate::{SelectedTileType, TileType};
+
+pub const MIN_BRUSH_RADIUS: u32 = 1;
+pub const MAX_BRUSH_RADIUS: u32 = 5;
+
+/// How a press-drag-release gesture turns into painted tiles.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PaintTool {
+    Brush,
+    Fill,
+    Rectangle,
+    RectangleOutline,
+    Line,
+}
+
+impl PaintTool {
+    pub const ALL: [PaintTool; 5] = [
+        PaintTool::Brush,
+        PaintTool::Fill,
+        PaintTool::Rectangle,
+        PaintTool::RectangleOutline,
+        PaintTool::Line,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaintTool::Brush => "Brush",
+            PaintTool::Fill => "Fill",
+            PaintTool::Rectangle => "Rect",
+            PaintTool::RectangleOutline => "Outline",
+            PaintTool::Line => "Line",
+        }
+    }
+}
+
+#[derive(Resource, Clone, Copy, PartialEq, Eq)]
+pub struct SelectedTool(pub PaintTool);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushShape {
+    Square,
+    Circle,
+}
+
+/// Footprint of the brush tool. A radius of 1 paints a single tile and each
+/// step adds a ring around it.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct BrushSettings {
+    pub shape: BrushShape,
+    pub radius: u32,
+}
+
+impl Default for BrushSettings {
+    fn default() -> Self {
+        BrushSettings {
+            shape: BrushShape::Square,
+            radius: MIN_BRUSH_RADIUS,
+        }
+    }
+}
+
+/// Marks the brush shape and size buttons in the tool bar.
+#[derive(Component, Clone, Copy, Debug)]
+pub enum BrushControl {
+    ToggleShape,
+    Shrink,
+    Grow,
+}
+
+#[derive(Component)]
+pub struct BrushText;
+
+/// Tile the cursor is over, refreshed every frame.
+#[derive(Resource, Default, PartialEq, Eq)]
+pub struct CursorTile(pub Option<(u32, u32)>);
+
+/// State of the gesture in progress.
+#[derive(Resource, Default)]
+pub struct ToolDrag {
+    /// Tile where the drag started, for tools that span two corners.
+    pub anchor: Option<(u32, u32)>,
+    /// Tile the brush painted last, so a stroke can be joined up with a line
+    /// when the cursor skips tiles between frames.
+    pub last: Option<(u32, u32)>,
+    /// Whether a brush stroke is in progress: the press landed on a tile and
+    /// did not harvest it. Leaving the grid clears `last` but not this.
+    pub stroking: bool,
+}
+
+impl ToolDrag {
+    /// Tiles the brush paints as a held stroke reaches `cursor`: a line from
+    /// the tile painted last, or just the footprint under the cursor when the
+    /// stroke comes back onto the grid, so the gap is not bridged. Nothing
+    /// when no stroke was started.
+    pub fn continue_stroke(
+        &mut self,
+        grid: &GridConfig,
+        cursor: (u32, u32),
+        brush: BrushSettings,
+    ) -> Vec<(u32, u32)> {
+        if !self.stroking {
+            return Vec::new();
+        }
+        match self.last.replace(cursor) {
+            Some(last) if last == cursor => Vec::new(),
+            Some(last) => line(last, cursor)
+                .into_iter()
+                .flat_map(|pos| brush_footprint(grid, pos, brush))
+                .collect(),
+            None => brush_footprint(grid, cursor, brush),
+        }
+    }
+}
+
+/// Tiles the active tool would paint if the mouse were released now.
+#[derive(Resource, Default, PartialEq, Eq)]
+pub struct ToolPreview(pub Vec<(u32, u32)>);
+
+#[derive(Component)]
+pub struct PreviewMarker;
+
+/// Outline drawn around [`ToolPreview`], above the tiles so their sprites
+/// only ever show the tile's own appearance. Its children are the edge
+/// segments.
+#[derive(Component)]
+pub struct HoverOutline;
+
+const GHOST_ALPHA: f32 = 0.5;
+const INVALID_GHOST_COLOR: Color = Color::rgba(0.9, 0.1, 0.1, 0.5);
+const OUTLINE_COLOR: Color = Color::YELLOW;
+const OUTLINE_WIDTH: f32 = 2.0;
+
+pub fn brush_footprint(
+    grid: &GridConfig,
+    center: (u32, u32),
+    brush: BrushSettings,
+) -> Vec<(u32, u32)> {
+    let reach = brush.radius.saturating_sub(1) as i32;
+    let mut tiles = Vec::new();
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            if brush.shape == BrushShape::Circle && dx * dx + dy * dy > reach * reach {
+                continue;
+            }
+            tiles.extend(offset(grid, center, dx, dy));
+        }
+    }
+    tiles
+}
+
+/// The 4-connected region of tiles sharing the type of the tile at `start`.
+/// The region stops at the edge of the loaded chunks.
+pub fn flood_fill(grid: &GridConfig, types: &TypeMap, start: (u32, u32)) -> Vec<(u32, u32)> {
+    let Some(&target) = types.get(&start) else {
+        return Vec::new();
+    };
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut region = Vec::new();
+
+    while let Some(pos) = queue.pop_front() {
+        region.push(pos);
+        for next in grid.neighbors(pos.0, pos.1) {
+            if types.get(&next) == Some(&target) && visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    region
+}
+
+pub fn rectangle(a: (u32, u32), b: (u32, u32), filled: bool) -> Vec<(u32, u32)> {
+    let (min_x, max_x) = (a.0.min(b.0), a.0.max(b.0));
+    let (min_y, max_y) = (a.1.min(b.1), a.1.max(b.1));
+    let mut tiles = Vec::new();
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let edge = x == min_x || x == max_x || y == min_y || y == max_y;
+            if filled || edge {
+                tiles.push((x, y));
+            }
+        }
+    }
+    tiles
+}
+
+/// Tiles on the Bresenham line from `a` to `b`, both ends included.
+pub fn line(a: (u32, u32), b: (u32, u32)) -> Vec<(u32, u32)> {
+    let (mut x, mut y) = (a.0 as i32, a.1 as i32);
+    let (end_x, end_y) = (b.0 as i32, b.1 as i32);
+    let dx = (end_x - x).abs();
+    let dy = -(end_y - y).abs();
+    let step_x = if x < end_x { 1 } else { -1 };
+    let step_y = if y < end_y { 1 } else { -1 };
+    let mut error = dx + dy;
+    let mut tiles = Vec::new();
+
+    loop {
+        tiles.push((x as u32, y as u32));
+        if x == end_x && y == end_y {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    tiles
+}
+
+fn offset(grid: &GridConfig, pos: (u32, u32), dx: i32, dy: i32) -> Option<(u32, u32)> {
+    let x = pos.0.checked_add_signed(dx)?;
+    let y = pos.1.checked_add_signed(dy)?;
+    grid.contains(x, y).then_some((x, y))
+}
+
+/// Tiles `tool` covers for a gesture from `anchor` to `cursor`.
+pub fn tool_footprint(
+    grid: &GridConfig,
+    tool: PaintTool,
+    brush: BrushSettings,
+    types: &TypeMap,
+    anchor: Option<(u32, u32)>,
+    cursor: (u32, u32),
+) -> Vec<(u32, u32)> {
+    let anchor = anchor.unwrap_or(cursor);
+    match tool {
+        PaintTool::Brush => brush_footprint(grid, cursor, brush),
+        PaintTool::Fill => flood_fill(grid, types, cursor),
+        PaintTool::Rectangle => rectangle(anchor, cursor, true),
+        PaintTool::RectangleOutline => rectangle(anchor, cursor, false),
+        PaintTool::Line => line(anchor, cursor),
+    }
+}
+
+pub fn tool_button_system(
+    interaction_query: Query<(&Interaction, &PaintTool), Changed<Interaction>>,
+    mut selected: ResMut<SelectedTool>,
+) {
+    for (interaction, tool) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            selected.0 = *tool;
+        }
+    }
+}
+
+pub fn brush_control_system(
+    interaction_query: Query<(&Interaction, &BrushControl), Changed<Interaction>>,
+    mut brush: ResMut<BrushSettings>,
+) {
+    for (interaction, control) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match control {
+            BrushControl::ToggleShape => {
+                brush.shape = match brush.shape {
+                    BrushShape::Square => BrushShape::Circle,
+                    BrushShape::Circle => BrushShape::Square,
+                };
+            }
+            BrushControl::Shrink => brush.radius = (brush.radius - 1).max(MIN_BRUSH_RADIUS),
+            BrushControl::Grow => brush.radius = (brush.radius + 1).min(MAX_BRUSH_RADIUS),
+        }
+    }
+}
+
+pub fn brush_text_system(brush: Res<BrushSettings>, mut texts: Query<&mut Text, With<BrushText>>) {
+    if !brush.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        text.sections[0].value = format!("{:?} r{}", brush.shape, brush.radius);
+    }
+}
+
+/// Shows a ghost of the selected tile type over [`ToolPreview`], tinted red
+/// on tiles where the placement rules would refuse it.
+pub fn preview_marker_system(
+    mut commands: Commands,
+    preview: Res<ToolPreview>,
+    selected: Res<SelectedTileType>,
+    grid: Res<GridConfig>,
+    tile_grid: Res<TileGrid>,
+    tiles: Query<&TileType>,
+    changed_tiles: Query<(), Changed<TileType>>,
+    markers: Query<Entity, With<PreviewMarker>>,
+) {
+    // Painting under the cursor can make the ghost valid or invalid without
+    // the footprint moving.
+    if !preview.is_changed() && !selected.is_changed() && changed_tiles.is_empty() {
+        return;
+    }
+    for entity in &markers {
+        commands.entity(entity).despawn();
+    }
+
+    let type_at = |x: u32, y: u32| tiles.get(tile_grid.get(x, y)?).ok().copied();
+    let ghost = selected.0.color().with_a(GHOST_ALPHA);
+    for &(x, y) in &preview.0 {
+        let allowed = type_at(x, y) == Some(selected.0)
+            || selected.0.can_be_placed_at(&grid, (x, y), type_at);
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: if allowed { ghost } else { INVALID_GHOST_COLOR },
+                    custom_size: Some(Vec2::splat(grid.tile_size)),
+                    ..default()
+                },
+                transform: Transform::from_translation(grid.tile_translation(x, y) + Vec3::Z),
+                ..default()
+            },
+            PreviewMarker,
+        ));
+    }
+}
+
+pub fn setup_hover_outline(mut commands: Commands) {
+    commands.spawn((
+        SpatialBundle::from_transform(Transform::from_translation(2.0 * Vec3::Z)),
+        HoverOutline,
+    ));
+}
+
+/// Traces the border of the tiles under the cursor, so a multi-tile brush
+/// shows its whole footprint rather than one highlighted tile.
+pub fn hover_outline_system(
+    mut commands: Commands,
+    preview: Res<ToolPreview>,
+    grid: Res<GridConfig>,
+    outlines: Query<Entity, With<HoverOutline>>,
+) {
+    if !preview.is_changed() {
+        return;
+    }
+    let footprint: HashSet<(u32, u32)> = preview.0.iter().copied().collect();
+    let half = grid.tile_size / 2.0;
+    let horizontal = Vec2::new(grid.tile_size + OUTLINE_WIDTH, OUTLINE_WIDTH);
+    let vertical = Vec2::new(OUTLINE_WIDTH, grid.tile_size + OUTLINE_WIDTH);
+
+    for outline in &outlines {
+        commands.entity(outline).despawn_descendants();
+        commands.entity(outline).with_children(|parent| {
+            for &(x, y) in &footprint {
+                // World y grows upwards, so north is the row above.
+                let sides = [
+                    (Some((x, y + 1)), Vec2::new(0.0, half), horizontal),
+                    (
+                        y.checked_sub(1).map(|y| (x, y)),
+                        Vec2::new(0.0, -half),
+                        horizontal,
+                    ),
+                    (Some((x + 1, y)), Vec2::new(half, 0.0), vertical),
+                    (
+                        x.checked_sub(1).map(|x| (x, y)),
+                        Vec2::new(-half, 0.0),
+                        vertical,
+                    ),
+                ];
+                for (neighbor, offset, size) in sides {
+                    if neighbor.is_some_and(|neighbor| footprint.contains(&neighbor)) {
+                        continue;
+                    }
+                    parent.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: OUTLINE_COLOR,
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(
+                            grid.tile_translation(x, y) + offset.extend(0.0),
+                        ),
+                        ..default()
+                    });
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(width: u32, height: u32) -> GridConfig {
+        GridConfig {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    fn sorted(mut tiles: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn line_includes_both_ends() {
+        assert_eq!(line((2, 3), (2, 3)), vec![(2, 3)]);
+        assert_eq!(line((0, 0), (3, 0)), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(line((3, 0), (0, 0)), vec![(3, 0), (2, 0), (1, 0), (0, 0)]);
+        assert_eq!(line((1, 4), (1, 2)), vec![(1, 4), (1, 3), (1, 2)]);
+    }
+
+    #[test]
+    fn line_steps_diagonally() {
+        assert_eq!(line((0, 0), (3, 3)), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert_eq!(line((3, 0), (0, 3)), vec![(3, 0), (2, 1), (1, 2), (0, 3)]);
+
+        // A shallow line still visits every column exactly once.
+        let shallow = line((0, 0), (6, 2));
+        assert_eq!(shallow.first(), Some(&(0, 0)));
+        assert_eq!(shallow.last(), Some(&(6, 2)));
+        assert_eq!(shallow.len(), 7);
+        assert!(shallow.windows(2).all(|pair| pair[1].0 == pair[0].0 + 1));
+    }
+
+    #[test]
+    fn rectangle_accepts_corners_in_any_order() {
+        let forward = sorted(rectangle((1, 1), (3, 2), true));
+        assert_eq!(forward.len(), 6);
+        assert_eq!(sorted(rectangle((3, 2), (1, 1), true)), forward);
+        assert_eq!(sorted(rectangle((1, 2), (3, 1), true)), forward);
+        assert_eq!(sorted(rectangle((3, 1), (1, 2), true)), forward);
+    }
+
+    #[test]
+    fn rectangle_outline_leaves_the_middle_empty() {
+        let outline = rectangle((4, 4), (0, 0), false);
+        assert_eq!(outline.len(), 16);
+        assert!(!outline.contains(&(2, 2)));
+        assert!(outline.contains(&(0, 4)) && outline.contains(&(4, 0)));
+        assert_eq!(rectangle((2, 2), (2, 2), false), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn brush_is_clipped_at_the_grid_edge() {
+        let grid = grid(5, 4);
+        let square = BrushSettings {
+            shape: BrushShape::Square,
+            radius: 2,
+        };
+        assert_eq!(brush_footprint(&grid, (2, 2), square).len(), 9);
+        assert_eq!(
+            sorted(brush_footprint(&grid, (0, 0), square)),
+            vec![(0, 0), (0, 1), (1, 0), (1, 1)]
+        );
+        assert_eq!(
+            sorted(brush_footprint(&grid, (4, 3), square)),
+            vec![(3, 2), (3, 3), (4, 2), (4, 3)]
+        );
+
+        let circle = BrushSettings {
+            shape: BrushShape::Circle,
+            radius: 2,
+        };
+        assert_eq!(
+            sorted(brush_footprint(&grid, (0, 0), circle)),
+            vec![(0, 0), (0, 1), (1, 0)]
+        );
+    }
+
+    #[test]
+    fn flood_fill_stops_at_other_types() {
+        let grid = grid(4, 3);
+        // Water splits the grass into a left and a right region.
+        let rows = ["..~.", "..~.", ".~~."];
+        let types: TypeMap = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(row, line)| {
+                line.chars().enumerate().map(move |(x, c)| {
+                    let tile_type = if c == '~' {
+                        TileType::Water
+                    } else {
+                        TileType::Grass
+                    };
+                    ((x as u32, 2 - row as u32), tile_type)
+                })
+            })
+            .collect();
+
+        let left = sorted(flood_fill(&grid, &types, (0, 2)));
+        assert_eq!(left, vec![(0, 0), (0, 1), (0, 2), (1, 1), (1, 2)]);
+        assert_eq!(
+            sorted(flood_fill(&grid, &types, (3, 0))),
+            vec![(3, 0), (3, 1), (3, 2)]
+        );
+        assert_eq!(flood_fill(&grid, &types, (2, 0)).len(), 4);
+        assert!(flood_fill(&grid, &TypeMap::new(), (0, 0)).is_empty());
+    }
+
+    #[test]
+    fn stroke_restarts_where_it_comes_back_onto_the_grid() {
+        let grid = grid(10, 10);
+        let brush = BrushSettings::default();
+        let mut drag = ToolDrag {
+            last: Some((1, 1)),
+            stroking: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            drag.continue_stroke(&grid, (3, 1), brush),
+            vec![(1, 1), (2, 1), (3, 1)]
+        );
+        assert!(drag.continue_stroke(&grid, (3, 1), brush).is_empty());
+
+        // The cursor left the grid, which forgets the last tile.
+        drag.last = None;
+        assert_eq!(drag.continue_stroke(&grid, (8, 6), brush), vec![(8, 6)]);
+        assert_eq!(
+            drag.continue_stroke(&grid, (8, 7), brush),
+            vec![(8, 6), (8, 7)]
+        );
+    }
+
+    #[test]
+    fn dragging_without_a_stroke_paints_nothing() {
+        // E.g. the press harvested a crop or landed on the UI.
+        let grid = grid(10, 10);
+        let mut drag = ToolDrag::default();
+        assert!(
+            drag.continue_stroke(&grid, (4, 4), BrushSettings::default())
+                .is_empty()
+        );
+        assert!(
+            drag.continue_stroke(&grid, (5, 4), BrushSettings::default())
+                .is_empty()
+        );
+    }
+}